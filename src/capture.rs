@@ -1,5 +1,5 @@
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Capture {
     prefix: Vec<String>,
     suffix: String,
@@ -43,6 +43,7 @@ impl Capture {
 }
 
 #[cfg(test)]
+#[allow(clippy::needless_borrow)]
 mod tests {
     use super::*;
 
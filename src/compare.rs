@@ -0,0 +1,176 @@
+// statistical comparison of two measurements, based on the standard error of their means
+use crate::database::{Entry, Measurement};
+
+// how many standard errors apart two means must be to be considered different
+const SIGNIFICANT: f64 = 3.0;
+const INSIGNIFICANT: f64 = 1.0;
+
+// a standard error bigger than this fraction of the reference is too noisy to call two means the same
+const NOISY: f64 = 0.05;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sample {
+    pub mean: f64,
+    pub stddev: f64,
+    pub runs: usize,
+}
+
+impl Sample {
+    // a fixed value, such as a threshold, has no error
+    pub fn exact(value: f64) -> Sample {
+        Sample { mean: value, stddev: 0.0, runs: 1 }
+    }
+
+    fn standard_error(&self) -> f64 {
+        self.stddev / (self.runs.max(1) as f64).sqrt()
+    }
+}
+
+impl From<&Entry> for Sample {
+    fn from(e: &Entry) -> Sample {
+        Sample { mean: e.mean, stddev: e.stddev, runs: e.runs }
+    }
+}
+
+impl From<&Measurement> for Sample {
+    fn from(m: &Measurement) -> Sample {
+        Sample { mean: m.mean, stddev: m.stddev, runs: m.runs }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Verdict {
+    Faster,
+    Same,
+    Slower,
+    Inconclusive,
+}
+
+// compare a sample against a reference, reverse when bigger is better.
+// Also returns how many standard errors apart they are
+pub fn compare(sample: &Sample, reference: &Sample, reverse: bool) -> (Verdict, f64) {
+    let diff = if reverse { reference.mean - sample.mean } else { sample.mean - reference.mean };
+    let error = sample.standard_error().hypot(reference.standard_error());
+
+    if error == 0.0 {
+        let verdict = match diff.partial_cmp(&0.0) {
+            Some(std::cmp::Ordering::Greater) => Verdict::Slower,
+            Some(std::cmp::Ordering::Less) => Verdict::Faster,
+            _ => Verdict::Same,
+        };
+        return (verdict, diff.signum() * f64::INFINITY);
+    }
+
+    let z = diff / error;
+    let verdict = if z >= SIGNIFICANT {
+        Verdict::Slower
+    } else if z <= -SIGNIFICANT {
+        Verdict::Faster
+    } else if z.abs() < INSIGNIFICANT && error <= NOISY * reference.mean.abs() {
+        Verdict::Same
+    } else {
+        Verdict::Inconclusive
+    };
+    (verdict, z)
+}
+
+// how much slower a sample may be than its reference
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Limit {
+    // a fraction of the reference mean
+    Relative(f64),
+    Absolute(f64),
+}
+
+// either a percentage like 5% or an absolute difference in the unit of the measurement
+pub fn parse_limit(value: &str) -> Result<Limit, String> {
+    let (number, percent) = match value.strip_suffix('%') {
+        Some(number) => (number, true),
+        None => (value, false),
+    };
+    let number = number.parse::<f64>().ok()
+        .filter(|n| n.is_finite() && *n >= 0.0)
+        .ok_or(format!("Bad limit: {}", value))?;
+    Ok(if percent { Limit::Relative(number / 100.0) } else { Limit::Absolute(number) })
+}
+
+impl Limit {
+    fn allowed(&self, reference: &Sample) -> f64 {
+        match self {
+            Limit::Relative(fraction) => fraction * reference.mean.abs(),
+            Limit::Absolute(value) => *value,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Regression {
+    Passed,
+    // significantly slower, but not by more than allowed
+    WithinLimit,
+    // slower by more than allowed, but that may be noise
+    WithinNoise,
+    Failed,
+}
+
+// a regression is both significantly slower and slower by more than the limit,
+// so a noisy measurement does not fail just because its mean happens to be high
+pub fn regression(sample: &Sample, reference: &Sample, limit: Limit, reverse: bool) -> Regression {
+    let diff = if reverse { reference.mean - sample.mean } else { sample.mean - reference.mean };
+    let (verdict, _) = compare(sample, reference, reverse);
+    match (verdict == Verdict::Slower, diff > limit.allowed(reference)) {
+        (true, true) => Regression::Failed,
+        (true, false) => Regression::WithinLimit,
+        (false, true) => Regression::WithinNoise,
+        (false, false) => Regression::Passed,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compare() {
+        let reference = Sample { mean: 1.0, stddev: 0.1, runs: 10 };
+        let slower = Sample { mean: 1.5, stddev: 0.1, runs: 10 };
+        let close = Sample { mean: 1.01, stddev: 0.1, runs: 10 };
+        let noisy = Sample { mean: 1.1, stddev: 0.5, runs: 4 };
+
+        assert_eq!(compare(&slower, &reference, false).0, Verdict::Slower);
+        assert_eq!(compare(&reference, &slower, false).0, Verdict::Faster);
+        assert_eq!(compare(&slower, &reference, true).0, Verdict::Faster);
+        assert_eq!(compare(&close, &reference, false).0, Verdict::Same);
+        assert_eq!(compare(&noisy, &reference, false).0, Verdict::Inconclusive);
+    }
+
+    #[test]
+    fn test_regression() {
+        let reference = Sample { mean: 1.0, stddev: 0.01, runs: 10 };
+        let slower = Sample { mean: 1.1, stddev: 0.01, runs: 10 };
+        let bit_slower = Sample { mean: 1.03, stddev: 0.01, runs: 10 };
+        let noisy = Sample { mean: 1.1, stddev: 0.5, runs: 3 };
+        let five = parse_limit("5%").unwrap();
+
+        assert_eq!(regression(&slower, &reference, five, false), Regression::Failed);
+        assert_eq!(regression(&bit_slower, &reference, five, false), Regression::WithinLimit);
+        assert_eq!(regression(&noisy, &reference, five, false), Regression::WithinNoise);
+        assert_eq!(regression(&reference, &slower, five, false), Regression::Passed);
+        assert_eq!(regression(&reference, &slower, five, true), Regression::Failed);
+        assert_eq!(regression(&slower, &reference, parse_limit("0.2").unwrap(), false), Regression::WithinLimit);
+
+        assert_eq!(five, Limit::Relative(0.05));
+        assert_eq!(parse_limit("0.01"), Ok(Limit::Absolute(0.01)));
+        assert!(parse_limit("-5%").is_err());
+        assert!(parse_limit("five").is_err());
+    }
+
+    #[test]
+    fn test_compare_exact() {
+        let threshold = Sample::exact(1.0);
+        assert_eq!(compare(&Sample::exact(1.2), &threshold, false).0, Verdict::Slower);
+        assert_eq!(compare(&Sample::exact(1.0), &threshold, false).0, Verdict::Same);
+        let sample = Sample { mean: 0.9, stddev: 0.01, runs: 5 };
+        assert_eq!(compare(&sample, &threshold, false).0, Verdict::Faster);
+    }
+}
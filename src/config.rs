@@ -1,51 +1,190 @@
 use std::env;
+use std::fs;
+use std::time::{SystemTime, UNIX_EPOCH};
+use std::ops::RangeInclusive;
+use std::path::Path;
 
 use crate::capture;
+use crate::compare::{self, Limit};
+use crate::regex::Regex;
+use crate::sched;
 
-#[derive(Debug,PartialEq)]
+#[derive(Debug,PartialEq,Clone)]
 pub enum FilterMode {
     All,
     Exe,
     Exact,
+    Args,
+    Name,
 }
 
-#[derive(Debug)]
+#[derive(Debug,PartialEq,Clone)]
+pub enum Action {
+    Run,
+    History,
+    Bisect,
+    Migrate,
+    MergeDriver,
+    Show,
+    List,
+    Stats,
+    Programs,
+    Delete,
+    Note,
+    Tag,
+    Prune,
+    Rename,
+    Baseline,
+}
+
+impl Action {
+    // maintenance commands work on the database only
+    pub fn needs_program(&self) -> bool {
+        matches!(self, Action::Run | Action::History | Action::Bisect)
+    }
+
+    // queries look at the database only, optionally for a single program
+    pub fn is_query(&self) -> bool {
+        matches!(self, Action::Show | Action::List | Action::Stats | Action::Programs)
+    }
+
+    // number of arguments the command takes before '--'
+    fn operands(&self) -> RangeInclusive<usize> {
+        match self {
+            Action::MergeDriver => 3..=3,
+            Action::Delete => 1..=usize::MAX,
+            Action::Note => 2..=2,
+            Action::Tag => 1..=usize::MAX,
+            Action::Rename => 0..=2,
+            Action::Baseline => 2..=2,
+            _ => 0..=0,
+        }
+    }
+}
+
+#[derive(Debug,Clone)]
 pub struct Config {
+    pub action: Action,
+    pub operands: Vec<String>,
     pub executable: String,
     pub arguments: Vec<String>,
     pub note: String,
+    pub name: Option<String>,
+    pub tags: Vec<String>,
+    pub labels: Vec<(String, String)>,
+    // tags and label keys the tag command removes
+    pub untags: Vec<String>,
+    pub unlabels: Vec<String>,
+    pub input: Option<String>,
+    pub cwd: Option<String>,
+    pub env: Vec<(String, String)>,
+    pub clean_env: bool,
+    pub seed: Option<u64>,
+    pub params: Vec<(String, String)>,
+    pub cpus: Vec<usize>,
+    pub nice: Option<i32>,
+    pub cooldown: f64,
+    pub noise_check: bool,
+    pub wait_for_idle: Option<f64>,
     pub capture: Option<capture::Capture>,
+    pub expect_output: Option<String>,
+    pub check_stable_output: bool,
     pub database: String,
     pub output: String,
     pub filter: FilterMode,
+    pub same_machine: bool,
     pub show: usize,
+    pub offset: usize,
+    pub since: Option<u64>,
+    pub until: Option<u64>,
+    pub note_contains: Option<String>,
+    pub args_contains: Option<String>,
+    pub program_regex: Option<Regex>,
+    pub has_tags: Vec<String>,
+    pub has_labels: Vec<(String, Option<String>)>,
     pub runs: usize,
     pub warmups: usize,
     pub ignore_failure: bool,
+    pub max_failures: Option<usize>,
     pub dry_run: bool,
+    pub force: bool,
+    pub yes: bool,
+    pub tolerant: bool,
     pub verbose: bool,
     pub reverse: bool,
     pub quiet: bool,
+    pub commits: String,
+    pub build: Option<String>,
+    pub baseline: Option<String>,
+    // another database to compare with, only ever read
+    pub baseline_db: Option<String>,
+    pub threshold: Option<f64>,
+    pub fail_if_slower: Option<Limit>,
+    pub older_than: Option<u64>,
+    pub keep_last: Option<usize>,
+    pub per_program: bool,
 }
 
 impl Default for Config {
     fn default() -> Self {
         Config {
+            action: Action::Run,
+            operands: Vec::new(),
             executable: String::new(),
             arguments: Vec::new(),
             note: String::new(),
+            name: None,
+            tags: Vec::new(),
+            labels: Vec::new(),
+            untags: Vec::new(),
+            unlabels: Vec::new(),
+            input: None,
+            cwd: None,
+            env: Vec::new(),
+            clean_env: false,
+            seed: None,
+            params: Vec::new(),
+            cpus: Vec::new(),
+            nice: None,
+            cooldown: 0.0,
+            noise_check: true,
+            wait_for_idle: None,
             capture: None,
+            expect_output: None,
+            check_stable_output: false,
             database: String::from("timing.mesa"),
             output: String::from("stdout.txt"),
             filter: FilterMode::Exe,
+            same_machine: false,
             show: 8,
+            offset: 0,
+            since: None,
+            until: None,
+            note_contains: None,
+            args_contains: None,
+            program_regex: None,
+            has_tags: Vec::new(),
+            has_labels: Vec::new(),
             runs: 3,
             warmups: 0,
             ignore_failure: false,
+            max_failures: None,
             dry_run: false,
+            force: false,
+            yes: false,
+            tolerant: false,
             verbose: false,
             reverse: false,
             quiet: false,
+            commits: String::new(),
+            build: None,
+            baseline: None,
+            baseline_db: None,
+            threshold: None,
+            fail_if_slower: None,
+            older_than: None,
+            keep_last: None,
+            per_program: false,
         }
     }
 }
@@ -54,31 +193,106 @@ impl Config {
     pub fn help() {
         let me = env::args().next().unwrap();
         eprintln!("
-Usage: {me} [mesa options] -- <program> [program arguments]
+Usage: {me} [command] [mesa options] -- <program> [program arguments]
+Commands
+    run                            benchmark the program, this is the default
+    history                        benchmark the program for a range of git commits
+    bisect                         for git bisect run, exit 0 if fast, 1 if slow and 125 if unsure
+    migrate                        upgrade the database to the current format
+    show                           show the latest entries, for the program if given
+    list                           show all entries, for the program if given
+    stats                          summarize all runs of each program
+    programs                       show the latest entry of each program
+    delete <id>...                 remove entries from the database
+    note <id> <note>               change the note of an entry
+    tag <id>...                    change the tags and labels of entries with --tag, --label, --untag and --unlabel
+    prune                          remove old entries from the database
+    rename <old> <new>             change the name of a benchmark
+    baseline set|clear <id>        pin the entry as the baseline of its program, or unpin it
+    rename --name=<name> -- <program> [program arguments]
+                                   name the entries of a program, filtered with exact unless --filter is given
+    merge-driver <base> <ours> <theirs>
+                                   git merge driver for databases, the result is written to <ours>
+
 Run options
     --database=<filename>          the database
     --note=<note>                  describe this run
+    --name=<name>                  name of the benchmark, entries with the same name are compared
+    --tag=<tag>                    tag this run, can be repeated
+    --label=<key>=<value>          label this run, can be repeated
     --runs=<number>                number of times target is run
     --warmups=<number>             number of warm up runs before the measurement
     --ignore                       ignore if application returned non-zero exit code
+    --max-failures=<number>        abort if more runs fail, implies --ignore
+    --expect-output=<filename>     fail if program output differs from this file
+    --check-stable-output          fail if program output differs between runs
+    --input=<filename>             feed this file to program stdin
+    --cwd=<directory>              run program in this directory
+    --env=<key>=<value>            set environment variable, can be repeated
+    --clean-env                    start program with an empty environment
+    --seed=<number>                seed passed to program as MESA_SEED
+    --param=<name>=<value>         parameter passed to program as MESA_PARAM_<name>
+    --cpus=<list>                  pin program to these cpus, e.g. 2,3 or 0-3 (Linux)
+    --nice=<number>                run program with this nice value (Linux)
+    --cooldown=<seconds>           sleep between runs
+    --wait-for-idle[=<score>]      wait until system noise is below score (default 0.2)
+    --no-noise-check               do not check the system for noise before running
+    --fail-if-slower=<limit>       exit with 3 if significantly slower than the pinned baseline or
+                                   previous entry, by more than e.g. 5% or an absolute value like 0.01
 
 Output options
     --output=<filename>            output file (CSV/JSON/TXT/XML/...) or stdout
     --show=<number>                max number of items to show
-    --filter=<mode>                filter mode: all, exe, exact
+    --offset=<number>              skip this many of the newest items
+    --since=<time>                 only entries from this time, e.g. 2024-05-01, 1714521600 or 7d (ago)
+    --until=<time>                 only entries from before this time
+    --note-contains=<text>         only entries with this text in the note
+    --args-contains=<text>         only entries with this text in the program arguments
+    --program-regex=<regex>        only entries where program and arguments match this regex
+    --has-tag=<tag>                only entries with this tag, can be repeated
+    --has-label=<key>[=<value>]    only entries with this label, can be repeated
+    --baseline=<id>|latest-tag:<tag>
+                                   compute changes against this entry instead of the pinned baseline or newest
+    --baseline-db=<filename>       also show matching entries from this database and compare against them,
+                                   it is never written to
+    --filter=<mode>[,machine]      filter mode: all, exe, exact, args (any program, same arguments) or
+                                   name (the default with --name), add machine for this machine only
 
 Record options
-    --dry-run                      do not save this run to the database
+    --dry-run                      do not save this run or change to the database
+    --tolerant                     skip database entries that can not be read
+    --force                        overwrite the database even if it could not be read
+    -y, --yes                      remove entries without asking for confirmation
     --reverse                      bigger is better, makes only sense with capture
     --capture=...                  capture from output, instead of measuring time
 
+History options
+    --commits=<range>              commits to benchmark, e.g. v1.0..main
+    --build=<command>              unmeasured command to run in each commit before benchmarking
+
+Bisect options
+    --baseline=<id>|latest-tag:<tag>
+                                   compare against this entry in the database
+    --threshold=<number>           compare against this value
+
+Tag options
+    --untag=<tag>                  remove this tag, can be repeated
+    --unlabel=<key>                remove this label, can be repeated
+
+Prune options
+    --older-than=<age>             remove entries older than this, e.g. 90d, 12h or 2w
+    --keep-last=<number>           keep this many of the newest entries, even if old
+    --per-program                  keep the newest entries of each program
+
 Misc
     --verbose                      be more verbose
-    -q, --quiet                    suppress progress display
+    -q, --quiet                    suppress progress display and warnings
 
 Examples:
     {me} --note=\"important stuff\" --warmups=5 --runs=10 --output=stdout.json -- sleep 1
     {me} --runs=1 --capture=\"/bogomips/://\" --output=stdout.table -- cat /proc/cpuinfo
+    {me} history --commits=v1.0..main --build=\"cargo build --release\" -- target/release/app
+    git bisect run {me} bisect --threshold=0.5 -- target/release/app
 ");
     }
 
@@ -91,30 +305,125 @@ Examples:
         let mut config = Config::default();
 
         let sep_pos = args.iter().position(|arg| arg == "--");
-        let mine = if let Some(pos) = sep_pos {
+        let mut mine = if let Some(pos) = sep_pos {
             &args[..pos]
         } else {
             &args[..]
         };
 
+        // the first argument may be a command
+        if let Some(command) = mine.first().filter(|arg| !arg.starts_with('-')) {
+            config.action = match &command[..] {
+                "run" => Action::Run,
+                "history" => Action::History,
+                "bisect" => Action::Bisect,
+                "migrate" => Action::Migrate,
+                "merge-driver" => Action::MergeDriver,
+                "show" => Action::Show,
+                "list" => Action::List,
+                "stats" => Action::Stats,
+                "programs" => Action::Programs,
+                "delete" => Action::Delete,
+                "note" => Action::Note,
+                "tag" => Action::Tag,
+                "prune" => Action::Prune,
+                "rename" => Action::Rename,
+                "baseline" => Action::Baseline,
+                _ => return Err(format!("Unknown command: {}", command)),
+            };
+            mine = &mine[1..];
+        }
+        if config.action == Action::List {
+            config.show = usize::MAX;
+        }
+
+        let mut filter_given = false;
         for arg in mine {
-            if let Some((key, value)) = arg.split_once('=') {
+            if !arg.starts_with('-') {
+                config.operands.push(arg.to_string());
+            } else if let Some((key, value)) = arg.split_once('=') {
                 match key {
                     "-d" | "--database" => config.database = value.to_string(),
                     "-o" | "--output" => config.output = value.to_string(),
                     "--note" => config.note = value.to_string(),
-                    "-f" | "--filter" => config.filter = match value {
-                        "all" => FilterMode::All,
-                        "exe" => FilterMode::Exe,
-                        "exact" => FilterMode::Exact,
-                        _ => return Err(format!("Unknown mode: {}", arg)),
+                    "--name" => config.name = Some(value.to_string()).filter(|name| !name.is_empty()),
+                    "--tag" if !value.is_empty() => config.tags.push(value.to_string()),
+                    "--label" => {
+                        let (k, v) = value.split_once('=').filter(|(k, _)| !k.is_empty())
+                            .ok_or(format!("Bad label: {}", arg))?;
+                        config.labels.push((k.to_string(), v.to_string()));
+                    },
+                    "--untag" if !value.is_empty() => config.untags.push(value.to_string()),
+                    "--unlabel" if !value.is_empty() => config.unlabels.push(value.to_string()),
+                    "-f" | "--filter" => for mode in value.split(',') {
+                        filter_given |= mode != "machine";
+                        match mode {
+                            "all" => config.filter = FilterMode::All,
+                            "exe" => config.filter = FilterMode::Exe,
+                            "exact" => config.filter = FilterMode::Exact,
+                            "args" => config.filter = FilterMode::Args,
+                            "name" => config.filter = FilterMode::Name,
+                            "machine" => config.same_machine = true,
+                            _ => return Err(format!("Unknown mode: {}", arg)),
+                        }
                     },
                     "-s" | "--show" =>
                         config.show = value.parse::<usize>().map_err(|_| format!("Bad number: {}", arg))?,
+                    "--offset" =>
+                        config.offset = value.parse::<usize>().map_err(|_| format!("Bad number: {}", arg))?,
+                    "--since" => config.since = Some(parse_time(value, now()).ok_or(format!("Bad time: {}", arg))?),
+                    "--until" => config.until = Some(parse_time(value, now()).ok_or(format!("Bad time: {}", arg))?),
+                    "--has-tag" => config.has_tags.push(value.to_string()),
+                    "--has-label" => config.has_labels.push(match value.split_once('=') {
+                        Some((k, v)) => (k.to_string(), Some(v.to_string())),
+                        None => (value.to_string(), None),
+                    }),
+                    "--note-contains" => config.note_contains = Some(value.to_string()),
+                    "--args-contains" => config.args_contains = Some(value.to_string()),
+                    "--program-regex" =>
+                        config.program_regex = Some(Regex::new(value).map_err(|e| format!("Bad regex {}: {}", arg, e))?),
                     "-r" | "--runs" =>
                         config.runs = value.parse::<usize>().map_err(|_| format!("Bad number: {}", arg))?,
                     "-w" | "--warmups" =>
                         config.warmups = value.parse::<usize>().map_err(|_| format!("Bad number: {}", arg))?,
+                    "--max-failures" => {
+                        let max = value.parse::<usize>().map_err(|_| format!("Bad number: {}", arg))?;
+                        config.max_failures = Some(max);
+                        config.ignore_failure = true;
+                    },
+                    "--input" => config.input = Some(value.to_string()),
+                    "--cwd" => config.cwd = Some(value.to_string()),
+                    "--env" => {
+                        let (k, v) = value.split_once('=').ok_or(format!("Bad environment variable: {}", arg))?;
+                        config.env.push((k.to_string(), v.to_string()));
+                    },
+                    "--seed" =>
+                        config.seed = Some(value.parse::<u64>().map_err(|_| format!("Bad number: {}", arg))?),
+                    "--param" => {
+                        let (k, v) = value.split_once('=').ok_or(format!("Bad parameter: {}", arg))?;
+                        config.params.push((k.to_string(), v.to_string()));
+                    },
+                    "--cpus" => config.cpus = sched::parse_cpus(value)?,
+                    "--nice" =>
+                        config.nice = Some(value.parse::<i32>().map_err(|_| format!("Bad number: {}", arg))?),
+                    "--cooldown" =>
+                        config.cooldown = value.parse::<f64>().ok().filter(|c| *c >= 0.0)
+                            .ok_or(format!("Bad number: {}", arg))?,
+                    "--wait-for-idle" =>
+                        config.wait_for_idle = Some(value.parse::<f64>().ok().filter(|w| w.is_finite() && *w > 0.0)
+                            .ok_or(format!("Bad number: {}", arg))?),
+                    "--expect-output" => config.expect_output = Some(value.to_string()),
+                    "--commits" => config.commits = value.to_string(),
+                    "--build" => config.build = Some(value.to_string()),
+                    "--baseline" => config.baseline = Some(value.to_string()),
+                    "--baseline-db" => config.baseline_db = Some(value.to_string()),
+                    "--threshold" =>
+                        config.threshold = Some(value.parse::<f64>().map_err(|_| format!("Bad number: {}", arg))?),
+                    "--older-than" =>
+                        config.older_than = Some(parse_age(value).ok_or(format!("Bad age: {}", arg))?),
+                    "--keep-last" =>
+                        config.keep_last = Some(value.parse::<usize>().map_err(|_| format!("Bad number: {}", arg))?),
+                    "--fail-if-slower" => config.fail_if_slower = Some(compare::parse_limit(value)?),
                     "--capture" => {
                         let pattern = capture::parse(value)?;
                         config.capture = Some(pattern)
@@ -129,30 +438,173 @@ Examples:
                     },
                     "-i" | "--ignore" => config.ignore_failure = true,
                     "-N" | "--dry-run" => config.dry_run = true,
+                    "--tolerant" => config.tolerant = true,
+                    "--force" => config.force = true,
+                    "-y" | "--yes" => config.yes = true,
                     "-V" | "--verbose" => config.verbose = true,
+                    "--clean-env" => config.clean_env = true,
+                    "--wait-for-idle" => config.wait_for_idle = Some(0.2),
+                    "--no-noise-check" => config.noise_check = false,
+                    "--check-stable-output" => config.check_stable_output = true,
                     "--reverse" => config.reverse = true,
+                    "--per-program" => config.per_program = true,
                     "-q" | "--quiet" => config.quiet = true,
                     _ => return Err(format!("Unknown flag: {}", arg)),
                 }
             }
         }
 
+        let operands = config.action.operands();
+        if !operands.contains(&config.operands.len()) {
+            return Err(match operands.end() {
+                0 => format!("Unexpected argument: {}", config.operands[0]),
+                _ if operands.start() == operands.end() => format!("This command needs {} arguments", operands.start()),
+                _ => format!("This command needs at least {} arguments", operands.start()),
+            });
+        }
+        if config.wait_for_idle.is_some() && !config.noise_check {
+            return Err("--wait-for-idle needs the noise check, it can't be used with --no-noise-check".to_string());
+        }
+        if config.fail_if_slower.is_some() && config.action != Action::Run {
+            return Err("--fail-if-slower only works when running a benchmark".to_string());
+        }
+        if let Some(filename) = &config.baseline_db {
+            if !matches!(config.action, Action::Run | Action::Show | Action::List) {
+                return Err("--baseline-db only works when running a benchmark or showing entries".to_string());
+            }
+            // the same file may be spelled differently, as long as they exist their real paths tell
+            let same = match (fs::canonicalize(filename), fs::canonicalize(&config.database)) {
+                (Ok(this), Ok(that)) => this == that,
+                _ => Path::new(filename) == Path::new(&config.database),
+            };
+            if same {
+                return Err("--baseline-db must be another database than --database".to_string());
+            }
+        }
+        if config.action == Action::Baseline && !["set", "clear"].contains(&config.operands[0].as_str()) {
+            return Err(format!("Unknown baseline command: {}", config.operands[0]));
+        }
+        let retag = !config.untags.is_empty() || !config.unlabels.is_empty();
+        if retag && config.action != Action::Tag {
+            return Err("--untag and --unlabel only work with the tag command".to_string());
+        }
+        if config.action == Action::Tag && !retag && config.tags.is_empty() && config.labels.is_empty() {
+            return Err("The tag command needs --tag, --label, --untag or --unlabel".to_string());
+        }
+        if config.action == Action::Prune && config.older_than.is_none() && config.keep_last.is_none() {
+            return Err("The prune command needs --older-than or --keep-last".to_string());
+        }
+        if config.per_program && config.keep_last.is_none() {
+            return Err("--per-program only makes sense with --keep-last".to_string());
+        }
+
+        // a named benchmark is compared with others of the same name, unless asked otherwise
+        if !filter_given && config.name.is_some() {
+            config.filter = if config.action == Action::Rename { FilterMode::Exact } else { FilterMode::Name };
+        }
+        // and the regression gate only compares a program with runs of the same arguments
+        if !filter_given && config.name.is_none() && config.fail_if_slower.is_some() {
+            config.filter = FilterMode::Exact;
+        }
+        if config.filter == FilterMode::Name && config.name.is_none() {
+            return Err("The name filter needs --name".to_string());
+        }
+
         let yours = if let Some(pos) = sep_pos {
             &args[pos + 1..]
         } else {
             &[]
         };
 
+        if config.action.is_query() && yours.is_empty() {
+            // without a program there is nothing to filter on, except the name
+            match config.filter {
+                FilterMode::Exe | FilterMode::Exact | FilterMode::Args if filter_given =>
+                    return Err("The exe, exact and args filters need a program, given after '--'".to_string()),
+                FilterMode::Name => (),
+                _ => config.filter = FilterMode::All,
+            }
+            return Ok(config);
+        }
+        if config.action == Action::Rename {
+            match (config.operands.len(), yours.is_empty()) {
+                (2, true) => return Ok(config),
+                (0, false) if config.name.is_some() => (),
+                _ => return Err("Use either rename <old> <new> or rename --name=<name> -- <program>".to_string()),
+            }
+        } else if !config.action.needs_program() && !config.action.is_query() {
+            if !yours.is_empty() {
+                return Err("This command does not run a program".to_string());
+            }
+            return Ok(config);
+        }
+
         if yours.is_empty() {
             return Err("The target program is missing. Use '--' to separate `mesa` options from the program to be executed.".to_string());
         }
 
+        if config.action == Action::History && config.commits.is_empty() {
+            return Err("The history command needs a range of commits, e.g. --commits=v1.0..main".to_string());
+        }
+        if config.action == Action::Bisect && config.baseline.is_none() == config.threshold.is_none() {
+            return Err("The bisect command needs either --baseline or --threshold".to_string());
+        }
+
         config.executable = yours[0].to_string();
         config.arguments = yours[1..].to_vec();
         Ok(config)
     }
 }
 
+// an age like 90d in seconds
+pub fn parse_age(value: &str) -> Option<u64> {
+    let unit = match value.chars().last()? {
+        's' => 1,
+        'm' => 60,
+        'h' => 60 * 60,
+        'd' => 60 * 60 * 24,
+        'w' => 60 * 60 * 24 * 7,
+        _ => return None,
+    };
+    value[..value.len() - 1].parse::<u64>().ok().map(|n| n * unit)
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+// a point in time as a timestamp, either a timestamp itself, a date and time in UTC
+// like 2024-05-01 or 2024-05-01T12:00 or an age like 7d relative to now
+pub fn parse_time(value: &str, now: u64) -> Option<u64> {
+    if let Some(age) = parse_age(value) {
+        return Some(now.saturating_sub(age));
+    }
+    if let Ok(timestamp) = value.parse::<u64>() {
+        return Some(timestamp);
+    }
+
+    let (date, time) = value.split_once(['T', ' ']).unwrap_or((value, "00:00"));
+    let date: Vec<u64> = date.split('-').map(|n| n.parse().ok()).collect::<Option<_>>()?;
+    let time: Vec<u64> = time.split(':').map(|n| n.parse().ok()).collect::<Option<_>>()?;
+    let (&[year, month, day], &[hour, minute, ref rest @ ..]) = (&date[..], &time[..]) else {
+        return None;
+    };
+    let second = match rest {
+        [] => 0,
+        [second] => *second,
+        _ => return None,
+    };
+    if year < 1970 || !(1..=12).contains(&month) || !(1..=31).contains(&day)
+        || hour > 23 || minute > 59 || second > 59 {
+        return None;
+    }
+
+    // days since 1970-01-01 in the proleptic gregorian calendar, with years starting in march
+    let (y, m) = if month <= 2 { (year - 1, month + 9) } else { (year, month - 3) };
+    let days = 365 * y + y / 4 - y / 100 + y / 400 + (153 * m + 2) / 5 + day - 1 - 719468;
+    Some(days * 86400 + hour * 3600 + minute * 60 + second)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -194,9 +646,342 @@ mod tests {
         assert_eq!(config.show, 10);
         assert_eq!(config.runs, 17);
         assert!(config.ignore_failure);
+        assert_eq!(config.max_failures, None);
         assert!(config.dry_run);
         assert_eq!(config.note, "this is just a test");
         assert_eq!(config.executable, "proggy");
         assert_eq!(config.arguments, vec!["arg1"]);
     }
+
+    #[test]
+    fn test_filter_machine() {
+        let args: Vec<String> = vec!["--filter=exact,machine", "--", "proggy"]
+            .into_iter().map(String::from).collect();
+        let config = Config::new(args).unwrap();
+        assert_eq!(config.filter, FilterMode::Exact);
+        assert!(config.same_machine);
+
+        let args: Vec<String> = vec!["--filter=machine", "--", "proggy"]
+            .into_iter().map(String::from).collect();
+        let config = Config::new(args).unwrap();
+        assert_eq!(config.filter, FilterMode::Exe);
+        assert!(config.same_machine);
+    }
+
+    #[test]
+    fn test_commands() {
+        let args: Vec<String> = vec!["--", "proggy"]
+            .into_iter().map(String::from).collect();
+        assert_eq!(Config::new(args).unwrap().action, Action::Run);
+
+        let args: Vec<String> = vec!["history", "--commits=a..b", "--build=make", "--", "proggy"]
+            .into_iter().map(String::from).collect();
+        let config = Config::new(args).unwrap();
+        assert_eq!(config.action, Action::History);
+        assert_eq!(config.commits, "a..b");
+        assert_eq!(config.build.as_deref(), Some("make"));
+
+        let args: Vec<String> = vec!["history", "--", "proggy"]
+            .into_iter().map(String::from).collect();
+        assert!(Config::new(args).is_err());
+
+        let args: Vec<String> = vec!["bisect", "--threshold=0.5", "--", "proggy"]
+            .into_iter().map(String::from).collect();
+        let config = Config::new(args).unwrap();
+        assert_eq!(config.action, Action::Bisect);
+        assert_eq!(config.threshold, Some(0.5));
+
+        let args: Vec<String> = vec!["bisect", "--", "proggy"]
+            .into_iter().map(String::from).collect();
+        assert!(Config::new(args).is_err());
+
+        let args: Vec<String> = vec!["migrate", "--database=old.mesa"]
+            .into_iter().map(String::from).collect();
+        let config = Config::new(args).unwrap();
+        assert_eq!(config.action, Action::Migrate);
+        assert_eq!(config.database, "old.mesa");
+
+        let args: Vec<String> = vec!["bogus", "--", "proggy"]
+            .into_iter().map(String::from).collect();
+        assert!(Config::new(args).is_err());
+
+        let args: Vec<String> = vec!["merge-driver", "base", "ours", "theirs"]
+            .into_iter().map(String::from).collect();
+        let config = Config::new(args).unwrap();
+        assert_eq!(config.action, Action::MergeDriver);
+        assert_eq!(config.operands, vec!["base", "ours", "theirs"]);
+
+        let args: Vec<String> = vec!["merge-driver", "base", "ours"]
+            .into_iter().map(String::from).collect();
+        assert!(Config::new(args).is_err());
+
+        let args: Vec<String> = vec!["show"]
+            .into_iter().map(String::from).collect();
+        let config = Config::new(args).unwrap();
+        assert_eq!(config.action, Action::Show);
+        assert_eq!(config.filter, FilterMode::All);
+
+        let args: Vec<String> = vec!["show", "--filter=machine"]
+            .into_iter().map(String::from).collect();
+        let config = Config::new(args).unwrap();
+        assert_eq!(config.filter, FilterMode::All);
+        assert!(config.same_machine);
+
+        for filter in ["--filter=exe", "--filter=exact", "--filter=args,machine"] {
+            let args: Vec<String> = vec!["stats", filter]
+                .into_iter().map(String::from).collect();
+            assert!(Config::new(args).is_err());
+        }
+
+        let args: Vec<String> = vec!["list", "--filter=exact", "--", "proggy", "arg"]
+            .into_iter().map(String::from).collect();
+        let config = Config::new(args).unwrap();
+        assert_eq!(config.filter, FilterMode::Exact);
+        assert_eq!(config.executable, "proggy");
+        assert_eq!(config.show, usize::MAX);
+
+        let args: Vec<String> = vec!["stats", "--show=2"]
+            .into_iter().map(String::from).collect();
+        assert_eq!(Config::new(args).unwrap().show, 2);
+
+        let args: Vec<String> = vec!["baseline", "set", "abc"]
+            .into_iter().map(String::from).collect();
+        assert_eq!(Config::new(args).unwrap().action, Action::Baseline);
+
+        let args: Vec<String> = vec!["baseline", "move", "abc"]
+            .into_iter().map(String::from).collect();
+        assert!(Config::new(args).is_err());
+
+        let args: Vec<String> = vec!["run", "stray", "--", "proggy"]
+            .into_iter().map(String::from).collect();
+        assert!(Config::new(args).is_err());
+    }
+
+    #[test]
+    fn test_editing() {
+        let args: Vec<String> = vec!["delete", "abc", "def"]
+            .into_iter().map(String::from).collect();
+        let config = Config::new(args).unwrap();
+        assert_eq!(config.action, Action::Delete);
+        assert_eq!(config.operands, vec!["abc", "def"]);
+
+        let args: Vec<String> = vec!["delete"]
+            .into_iter().map(String::from).collect();
+        assert!(Config::new(args).is_err());
+
+        let args: Vec<String> = vec!["note", "abc", "a better note, with -- and = in it"]
+            .into_iter().map(String::from).collect();
+        assert_eq!(Config::new(args).unwrap().operands[1], "a better note, with -- and = in it");
+
+        let args: Vec<String> = vec!["prune", "--older-than=90d", "--keep-last=3", "--per-program"]
+            .into_iter().map(String::from).collect();
+        let config = Config::new(args).unwrap();
+        assert_eq!(config.older_than, Some(90 * 24 * 3600));
+        assert_eq!(config.keep_last, Some(3));
+        assert!(config.per_program);
+
+        let args: Vec<String> = vec!["prune"]
+            .into_iter().map(String::from).collect();
+        assert!(Config::new(args).is_err());
+
+        let args: Vec<String> = vec!["tag", "0badc0de", "1700000000", "--tag=lto", "--untag=pgo", "--unlabel=cc", "--yes"]
+            .into_iter().map(String::from).collect();
+        let config = Config::new(args).unwrap();
+        assert_eq!(config.action, Action::Tag);
+        assert_eq!(config.operands, vec!["0badc0de", "1700000000"]);
+        assert_eq!(config.tags, vec!["lto"]);
+        assert_eq!(config.untags, vec!["pgo"]);
+        assert_eq!(config.unlabels, vec!["cc"]);
+        assert!(config.yes);
+
+        let args: Vec<String> = vec!["tag", "0badc0de"]
+            .into_iter().map(String::from).collect();
+        assert!(Config::new(args).is_err());
+
+        let args: Vec<String> = vec!["--untag=pgo", "--", "proggy"]
+            .into_iter().map(String::from).collect();
+        assert!(Config::new(args).is_err());
+
+        assert_eq!(parse_age("2w"), Some(14 * 24 * 3600));
+        assert_eq!(parse_age("15m"), Some(900));
+        assert_eq!(parse_age("15"), None);
+        assert_eq!(parse_age("d"), None);
+    }
+
+    #[test]
+    fn test_search_options() {
+        let args: Vec<String> = vec!["list", "--since=2024-05-01", "--until=2024-06-01T12:30", "--offset=2",
+                                     "--note-contains=lto", "--args-contains=30", "--program-regex=^py(thon|py)"]
+            .into_iter().map(String::from).collect();
+        let config = Config::new(args).unwrap();
+        assert_eq!(config.since, Some(1714521600));
+        assert_eq!(config.until, Some(1717245000));
+        assert_eq!(config.offset, 2);
+        assert_eq!(config.note_contains.as_deref(), Some("lto"));
+        assert_eq!(config.args_contains.as_deref(), Some("30"));
+        assert!(config.program_regex.unwrap().is_match("pypy3 fib.py"));
+
+        let args: Vec<String> = vec!["list", "--program-regex=(py"]
+            .into_iter().map(String::from).collect();
+        assert!(Config::new(args).is_err());
+
+        assert_eq!(parse_time("7d", 1000000), Some(1000000 - 7 * 86400));
+        assert_eq!(parse_time("1714521600", 0), Some(1714521600));
+        assert_eq!(parse_time("1970-01-01", 0), Some(0));
+        assert_eq!(parse_time("2000-03-01 00:00:01", 0), Some(951868801));
+        assert_eq!(parse_time("2024-13-01", 0), None);
+        assert_eq!(parse_time("2024-05", 0), None);
+        assert_eq!(parse_time("yesterday", 0), None);
+    }
+
+    #[test]
+    fn test_names() {
+        let args: Vec<String> = vec!["--name=fib", "--", "python3", "fib.py"]
+            .into_iter().map(String::from).collect();
+        let config = Config::new(args).unwrap();
+        assert_eq!(config.name.as_deref(), Some("fib"));
+        assert_eq!(config.filter, FilterMode::Name);
+
+        let args: Vec<String> = vec!["--name=fib", "--filter=exe,machine", "--", "python3", "fib.py"]
+            .into_iter().map(String::from).collect();
+        assert_eq!(Config::new(args).unwrap().filter, FilterMode::Exe);
+
+        let args: Vec<String> = vec!["--filter=name", "--", "python3", "fib.py"]
+            .into_iter().map(String::from).collect();
+        assert!(Config::new(args).is_err());
+
+        let args: Vec<String> = vec!["show", "--name=fib"]
+            .into_iter().map(String::from).collect();
+        assert_eq!(Config::new(args).unwrap().filter, FilterMode::Name);
+
+        let args: Vec<String> = vec!["rename", "fib", "fibonacci"]
+            .into_iter().map(String::from).collect();
+        assert_eq!(Config::new(args).unwrap().operands, vec!["fib", "fibonacci"]);
+
+        let args: Vec<String> = vec!["rename", "--name=fib", "--", "python3", "fib.py"]
+            .into_iter().map(String::from).collect();
+        let config = Config::new(args).unwrap();
+        assert_eq!(config.filter, FilterMode::Exact);
+        assert_eq!(config.executable, "python3");
+
+        for args in [vec!["rename", "fib"], vec!["rename", "--", "python3"], vec!["rename", "a", "b", "--", "python3"]] {
+            let args: Vec<String> = args.into_iter().map(String::from).collect();
+            assert!(Config::new(args).is_err());
+        }
+    }
+
+    #[test]
+    fn test_tags() {
+        let args: Vec<String> = vec!["--tag=lto", "--tag=pgo", "--label=compiler=gcc-14", "--has-tag=lto",
+                                     "--has-label=compiler", "--has-label=opt=O3", "--", "proggy"]
+            .into_iter().map(String::from).collect();
+        let config = Config::new(args).unwrap();
+        assert_eq!(config.tags, vec!["lto", "pgo"]);
+        assert_eq!(config.labels, vec![("compiler".to_string(), "gcc-14".to_string())]);
+        assert_eq!(config.has_tags, vec!["lto"]);
+        assert_eq!(config.has_labels, vec![("compiler".to_string(), None), ("opt".to_string(), Some("O3".to_string()))]);
+
+        for arg in ["--tag=", "--label=compiler", "--label==x"] {
+            let args: Vec<String> = vec![arg, "--", "proggy"]
+                .into_iter().map(String::from).collect();
+            assert!(Config::new(args).is_err());
+        }
+    }
+
+    #[test]
+    fn test_baseline_db() {
+        let args: Vec<String> = vec!["--baseline-db=main.mesa", "--", "proggy"]
+            .into_iter().map(String::from).collect();
+        assert_eq!(Config::new(args).unwrap().baseline_db.as_deref(), Some("main.mesa"));
+
+        let args: Vec<String> = vec!["list", "--baseline-db=main.mesa"]
+            .into_iter().map(String::from).collect();
+        assert!(Config::new(args).is_ok());
+
+        for args in [vec!["prune", "--keep-last=1", "--baseline-db=main.mesa"],
+                     vec!["--baseline-db=timing.mesa", "--", "proggy"]] {
+            let args: Vec<String> = args.into_iter().map(String::from).collect();
+            assert!(Config::new(args).is_err());
+        }
+
+        let dir = env::temp_dir();
+        let file = dir.join(format!("mesa-same-{}.mesa", std::process::id()));
+        fs::write(&file, "").unwrap();
+        let other = dir.join(".").join(file.file_name().unwrap());
+        let args = vec![format!("--database={}", file.display()), format!("--baseline-db={}", other.display()),
+                        "--".to_string(), "proggy".to_string()];
+        let same = Config::new(args);
+        fs::remove_file(&file).unwrap();
+        assert!(same.is_err());
+    }
+
+    #[test]
+    fn test_max_failures() {
+        let args: Vec<String> = vec!["--max-failures=2", "--", "proggy"]
+            .into_iter().map(String::from).collect();
+        let config = Config::new(args).unwrap();
+        assert_eq!(config.max_failures, Some(2));
+        assert!(config.ignore_failure);
+    }
+
+    #[test]
+    fn test_fail_if_slower() {
+        let args: Vec<String> = vec!["--fail-if-slower=5%", "--", "proggy"]
+            .into_iter().map(String::from).collect();
+        let config = Config::new(args).unwrap();
+        assert_eq!(config.fail_if_slower, Some(Limit::Relative(0.05)));
+        assert_eq!(config.filter, FilterMode::Exact);
+
+        let args: Vec<String> = vec!["--fail-if-slower=5%", "--filter=exe", "--", "proggy"]
+            .into_iter().map(String::from).collect();
+        assert_eq!(Config::new(args).unwrap().filter, FilterMode::Exe);
+
+        let args: Vec<String> = vec!["list", "--fail-if-slower=5%"]
+            .into_iter().map(String::from).collect();
+        assert!(Config::new(args).is_err());
+    }
+
+    #[test]
+    fn test_noise_check() {
+        let parse = |args: &[&str]| Config::new(args.iter().map(|a| a.to_string()).collect());
+        assert!(parse(&["--", "proggy"]).unwrap().noise_check);
+        assert!(!parse(&["--no-noise-check", "--", "proggy"]).unwrap().noise_check);
+
+        let config = parse(&["--wait-for-idle=0.5", "--", "proggy"]).unwrap();
+        assert!(config.noise_check);
+        assert_eq!(config.wait_for_idle, Some(0.5));
+        assert_eq!(parse(&["--wait-for-idle", "--", "proggy"]).unwrap().wait_for_idle, Some(0.2));
+
+        for bad in ["--wait-for-idle=0", "--wait-for-idle=-1", "--wait-for-idle=inf", "--wait-for-idle=NaN"] {
+            assert!(parse(&[bad, "--", "proggy"]).is_err());
+        }
+        assert!(parse(&["--wait-for-idle", "--no-noise-check", "--", "proggy"]).is_err());
+    }
+
+    #[test]
+    fn test_environment() {
+        let args: Vec<String> = vec!["--env=A=1", "--env=B=x=y", "--clean-env", "--cwd=/tmp", "--", "proggy"]
+            .into_iter().map(String::from).collect();
+        let config = Config::new(args).unwrap();
+        assert_eq!(config.env, vec![("A".to_string(), "1".to_string()), ("B".to_string(), "x=y".to_string())]);
+        assert!(config.clean_env);
+        assert_eq!(config.cwd.as_deref(), Some("/tmp"));
+
+        let args: Vec<String> = vec!["--env=A", "--", "proggy"]
+            .into_iter().map(String::from).collect();
+        assert!(Config::new(args).is_err());
+    }
+
+    #[test]
+    fn test_run_metadata() {
+        let args: Vec<String> = vec!["--seed=42", "--param=size=large", "--", "proggy"]
+            .into_iter().map(String::from).collect();
+        let config = Config::new(args).unwrap();
+        assert_eq!(config.seed, Some(42));
+        assert_eq!(config.params, vec![("size".to_string(), "large".to_string())]);
+
+        let args: Vec<String> = vec!["--seed=x", "--", "proggy"]
+            .into_iter().map(String::from).collect();
+        assert!(Config::new(args).is_err());
+    }
 }
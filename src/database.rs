@@ -1,31 +1,143 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::Path;
 use std::str::FromStr;
 use std::time::{SystemTime, UNIX_EPOCH};
 
 use crate::config::{Config, FilterMode};
+use crate::git::{self, Revision};
+use crate::system::Machine;
+use crate::verify;
 
-const DB_HEADER: &str = "# mesa database|github.com/avahidi/mesa|version=1.2";
+const DB_HEADER: &str = "# mesa database|github.com/avahidi/mesa|version=";
+const DB_VERSION: &str = "1.3";
 
-#[derive(Debug)]
+// older versions we can still read, they are upgraded when saved
+const DB_VERSION_1_2: &str = "1.2";
+
+#[derive(Debug, Default, Clone)]
 pub struct Entry {
+    pub id: String,
     pub timestamp: u64,
+    pub name: String,
     pub executable: String,
     pub arguments: String,
     pub note: String,
+    pub tags: Vec<String>,
+    pub labels: Vec<(String, String)>,
+    // pinned as the reference of its program
+    pub baseline: bool,
     pub runs: usize,
     pub mean: f64,
     pub stddev: f64,
+    pub failures: usize,
+    pub exit_codes: Vec<i32>,
+    pub output_hash: Option<u64>,
+    pub mismatches: usize,
+    pub input: String,
+    pub cwd: String,
+    pub env: Vec<String>,
+    pub clean_env: bool,
+    pub seed: Option<u64>,
+    pub params: Vec<String>,
+    pub cpus: Vec<usize>,
+    pub nice: Option<i32>,
+    pub cooldown: f64,
+    pub noise: Option<f64>,
+    pub machine: Machine,
+    pub version: String,
+    pub git: Revision,
+    pub extra: Vec<(String, String)>,
+}
+
+// outcome of a benchmark, runs only counts the successful ones
+#[derive(Debug, Default)]
+pub struct Measurement {
+    pub mean: f64,
+    pub stddev: f64,
+    pub runs: usize,
+    pub failures: usize,
+    pub exit_codes: Vec<i32>,
+    pub output_hash: Option<u64>,
+    pub mismatches: usize,
+    pub noise: Option<f64>,
 }
 
 impl FromStr for Entry {
     type Err = String;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let parts: Vec<&str> = s.split('|').collect();
-        if parts.len() != 7 {
-            return Err(format!("Invalid entry format: {}", s));
+        Entry::parse(s, DB_VERSION)
+    }
+}
+
+// '|' separates fields, so it and anything that would break the line must be escaped
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('|', "\\|").replace('\n', "\\n").replace('\r', "\\r")
+}
+
+// split on '|' unless escaped, and undo the escaping
+fn split_escaped(s: &str) -> Result<Vec<String>, String> {
+    let mut fields = vec![String::new()];
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        let field = fields.last_mut().unwrap();
+        match c {
+            '|' => fields.push(String::new()),
+            '\\' => match chars.next() {
+                Some('\\') => field.push('\\'),
+                Some('|') => field.push('|'),
+                Some('n') => field.push('\n'),
+                Some('r') => field.push('\r'),
+                Some(other) => return Err(format!("Invalid escape sequence: \\{}", other)),
+                None => return Err("Entry ends with an escape character".to_string()),
+            },
+            _ => field.push(c),
+        }
+    }
+    Ok(fields)
+}
+
+impl fmt::Display for Entry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}|{}|{}|{}|{}|{}|{}",
+                self.timestamp, escape(&self.executable), escape(&self.arguments), self.runs,
+                self.mean, self.stddev, escape(&self.note),
+        )?;
+
+        for (key, value) in self.fields() {
+            write!(f, "|{}={}", key, escape(&value))?;
         }
+        Ok(())
+    }
+}
 
-        Ok(Entry {
+impl Entry {
+    // parse an entry written by the given database version
+    pub fn parse(line: &str, version: &str) -> Result<Entry, String> {
+        let mut entry = Entry::parse_stored(line, version)?;
+        // entries from before ids were stored get one based on what they measured
+        if entry.id.is_empty() {
+            entry.id = entry.derive_id();
+        }
+        Ok(entry)
+    }
+
+    // the entry as it was stored, without an id if it was written before ids were stored
+    fn parse_stored(line: &str, version: &str) -> Result<Entry, String> {
+        // 1.2 had neither escaping nor optional fields, so a | after the note belongs to the note
+        let parts = if version == DB_VERSION_1_2 {
+            line.splitn(7, '|').map(String::from).collect()
+        } else {
+            split_escaped(line)?
+        };
+        if parts.len() < 7 {
+            return Err(format!("Invalid entry format: {}", line));
+        }
+
+        let mut entry = Entry {
             timestamp: parts[0].parse().map_err(|e| format!("Invalid timestamp: {}", e))?,
             executable: parts[1].to_string(),
             arguments: parts[2].to_string(),
@@ -33,20 +145,210 @@ impl FromStr for Entry {
             mean: parts[4].parse().map_err(|e| format!("Invalid stored mean: {}", e))?,
             stddev: parts[5].parse().map_err(|e| format!("Invalid stored std dev: {}", e))?,
             note: parts[6].to_string(),
-        })
+            ..Default::default()
+        };
+
+        // anything after the note is an optional key=value field
+        for field in &parts[7..] {
+            let (key, value) = field.split_once('=')
+                .ok_or_else(|| format!("Invalid entry field: {}", field))?;
+            match key {
+                "id" => entry.id = value.to_string(),
+                "name" => entry.name = value.to_string(),
+                "tag" => entry.tags.push(value.to_string()),
+                "baseline" => entry.baseline = value == "1",
+                "label" => entry.labels.push(value.split_once('=')
+                    .map(|(k, v)| (k.to_string(), v.to_string()))
+                    .ok_or_else(|| format!("Invalid label: {}", value))?),
+                "failures" => entry.failures = value.parse().map_err(|e| format!("Invalid failure count: {}", e))?,
+                "codes" => entry.exit_codes = value.split(',')
+                    .map(|c| c.parse::<i32>())
+                    .collect::<Result<_, _>>()
+                    .map_err(|e| format!("Invalid exit code: {}", e))?,
+                "output" => entry.output_hash = Some(u64::from_str_radix(value, 16)
+                    .map_err(|e| format!("Invalid output hash: {}", e))?),
+                "mismatches" => entry.mismatches = value.parse().map_err(|e| format!("Invalid mismatch count: {}", e))?,
+                "input" => entry.input = value.to_string(),
+                "cwd" => entry.cwd = value.to_string(),
+                "env" => entry.env.push(value.to_string()),
+                "clean_env" => entry.clean_env = value == "1",
+                "seed" => entry.seed = Some(value.parse().map_err(|e| format!("Invalid seed: {}", e))?),
+                "param" => entry.params.push(value.to_string()),
+                "cpus" => entry.cpus = value.split(',')
+                    .map(|c| c.parse::<usize>())
+                    .collect::<Result<_, _>>()
+                    .map_err(|e| format!("Invalid cpu: {}", e))?,
+                "nice" => entry.nice = Some(value.parse().map_err(|e| format!("Invalid nice value: {}", e))?),
+                "cooldown" => entry.cooldown = value.parse().map_err(|e| format!("Invalid cooldown: {}", e))?,
+                "noise" => entry.noise = Some(value.parse().map_err(|e| format!("Invalid noise score: {}", e))?),
+                "host" => entry.machine.hostname = value.to_string(),
+                "kernel" => entry.machine.kernel = value.to_string(),
+                "cpu" => entry.machine.cpu = value.to_string(),
+                "cores" => entry.machine.cores = value.parse().map_err(|e| format!("Invalid core count: {}", e))?,
+                "memory" => entry.machine.memory = value.parse().map_err(|e| format!("Invalid memory size: {}", e))?,
+                "mesa" => entry.version = value.to_string(),
+                "commit" => entry.git.commit = value.to_string(),
+                "branch" => entry.git.branch = value.to_string(),
+                "dirty" => entry.git.dirty = value == "1",
+                // written by a newer mesa, keep it as is
+                _ => entry.extra.push((key.to_string(), value.to_string())),
+            }
+        }
+        Ok(entry)
     }
-}
 
-impl ToString for Entry {
-    fn to_string(&self) -> String {
-        format!("{}|{}|{}|{}|{}|{}|{}",
-                self.timestamp, self.executable, self.arguments, self.runs,
-                self.mean, self.stddev, self.note,
-        )
+    // the optional key=value fields, anything left out has its default value
+    fn fields(&self) -> Vec<(&str, String)> {
+        let mut fields = Vec::new();
+        if !self.id.is_empty() {
+            fields.push(("id", self.id.clone()));
+        }
+        if !self.name.is_empty() {
+            fields.push(("name", self.name.clone()));
+        }
+        fields.extend(self.tags.iter().map(|tag| ("tag", tag.clone())));
+        fields.extend(self.labels.iter().map(|(k, v)| ("label", format!("{}={}", k, v))));
+        if self.baseline {
+            fields.push(("baseline", "1".to_string()));
+        }
+        if self.failures > 0 {
+            let codes: Vec<String> = self.exit_codes.iter().map(|c| c.to_string()).collect();
+            fields.push(("failures", self.failures.to_string()));
+            fields.push(("codes", codes.join(",")));
+        }
+        if let Some(hash) = self.output_hash {
+            fields.push(("output", format!("{:016x}", hash)));
+        }
+        if self.mismatches > 0 {
+            fields.push(("mismatches", self.mismatches.to_string()));
+        }
+        if !self.input.is_empty() {
+            fields.push(("input", self.input.clone()));
+        }
+        if !self.cwd.is_empty() {
+            fields.push(("cwd", self.cwd.clone()));
+        }
+        if self.clean_env {
+            fields.push(("clean_env", "1".to_string()));
+        }
+        fields.extend(self.env.iter().map(|var| ("env", var.clone())));
+        if let Some(seed) = self.seed {
+            fields.push(("seed", seed.to_string()));
+        }
+        fields.extend(self.params.iter().map(|param| ("param", param.clone())));
+        if !self.cpus.is_empty() {
+            fields.push(("cpus", self.cpu_list()));
+        }
+        if let Some(nice) = self.nice {
+            fields.push(("nice", nice.to_string()));
+        }
+        if self.cooldown > 0.0 {
+            fields.push(("cooldown", self.cooldown.to_string()));
+        }
+        if let Some(noise) = self.noise {
+            fields.push(("noise", format!("{:.2}", noise)));
+        }
+        if !self.machine.hostname.is_empty() {
+            fields.push(("host", self.machine.hostname.clone()));
+        }
+        if !self.machine.kernel.is_empty() {
+            fields.push(("kernel", self.machine.kernel.clone()));
+        }
+        if !self.machine.cpu.is_empty() {
+            fields.push(("cpu", self.machine.cpu.clone()));
+        }
+        if self.machine.cores > 0 {
+            fields.push(("cores", self.machine.cores.to_string()));
+        }
+        if self.machine.memory > 0 {
+            fields.push(("memory", self.machine.memory.to_string()));
+        }
+        if !self.version.is_empty() {
+            fields.push(("mesa", self.version.clone()));
+        }
+        if !self.git.commit.is_empty() {
+            fields.push(("commit", self.git.commit.clone()));
+        }
+        if !self.git.branch.is_empty() {
+            fields.push(("branch", self.git.branch.clone()));
+        }
+        if self.git.dirty {
+            fields.push(("dirty", "1".to_string()));
+        }
+        fields.extend(self.extra.iter().map(|(k, v)| (k.as_str(), v.clone())));
+        fields
+    }
+
+    pub fn label(&self, key: &str) -> Option<&str> {
+        self.labels.iter().find(|(k, _)| k == key).map(|(_, v)| v.as_str())
+    }
+
+    // add and remove the tags and labels given to the tag command, true if anything changed
+    pub fn retag(&mut self, cfg: &Config) -> bool {
+        let before = self.tag_list();
+        self.tags.retain(|t| !cfg.untags.contains(t));
+        self.labels.retain(|(k, _)| !cfg.unlabels.contains(k));
+        for tag in &cfg.tags {
+            if !self.tags.contains(tag) {
+                self.tags.push(tag.clone());
+            }
+        }
+        for (key, value) in &cfg.labels {
+            match self.labels.iter_mut().find(|(k, _)| k == key) {
+                Some((_, v)) => *v = value.clone(),
+                None => self.labels.push((key.clone(), value.clone())),
+            }
+        }
+        self.tag_list() != before
+    }
+
+    // tags and labels as shown in the table
+    pub fn tag_list(&self) -> String {
+        let labels = self.labels.iter().map(|(k, v)| format!("{}={}", k, v));
+        self.tags.iter().cloned().chain(labels).collect::<Vec<_>>().join(" ")
+    }
+
+    pub fn cpu_list(&self) -> String {
+        self.cpus.iter().map(|c| c.to_string()).collect::<Vec<_>>().join(",")
+    }
+
+    // scheduling setup of the runs, entries with different setups are not directly comparable
+    pub fn setup(&self) -> String {
+        let mut setup = Vec::new();
+        if !self.cpus.is_empty() {
+            setup.push(format!("cpus={}", self.cpu_list()));
+        }
+        if let Some(nice) = self.nice {
+            setup.push(format!("nice={}", nice));
+        }
+        if self.cooldown > 0.0 {
+            setup.push(format!("cooldown={}", self.cooldown));
+        }
+        setup.join(" ")
+    }
+
+    // id for an entry that was stored without one, derived from the measurement so
+    // it is the same every time the entry is read until it is stored
+    fn derive_id(&self) -> String {
+        let key = format!("{}|{}|{}|{}|{}|{}", self.timestamp, self.executable, self.arguments,
+                          self.runs, self.mean, self.stddev);
+        format!("{:08x}", verify::hash(&key) as u32)
+    }
+
+    // the program as it is shown
+    pub fn program(&self) -> String {
+        format!("{} {}", self.executable, self.arguments)
+    }
+
+    // entries with the same key are measuring the same program, even if run from another directory.
+    // A named benchmark is the same whatever command it was run with
+    pub fn program_key(&self) -> String {
+        if !self.name.is_empty() {
+            return format!("name:{}", self.name);
+        }
+        format!("{} {}", normalize_executable(&self.executable), normalize_arguments(&self.arguments))
     }
-}
 
-impl Entry {
     pub fn age(&self, from: u64) -> String {
         let diff_secs = from.saturating_sub(self.timestamp);
         if diff_secs == 0 {
@@ -65,9 +367,44 @@ impl Entry {
     }
 }
 
+// ./a.out, a.out and /abs/path/a.out are all the same program
+pub fn normalize_executable(executable: &str) -> &str {
+    executable.rsplit('/').next().unwrap_or(executable)
+}
+
+// python3 ./x.py and python3 x.py run the same script
+pub fn normalize_arguments(arguments: &str) -> String {
+    arguments.split(' ')
+        .map(|arg| arg.strip_prefix("./").filter(|a| !a.is_empty()).unwrap_or(arg))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
 pub struct Database {
     pub entries: Vec<Entry>,
     filename: String,
+    // version of the file as loaded, None if there was no file
+    pub version: Option<String>,
+    // skip entries that can't be parsed instead of failing
+    pub tolerant: bool,
+    // allow saving a database that failed to load
+    pub force: bool,
+    broken: bool,
+    // entries were changed or removed, not just added
+    edited: bool,
+    // opened with read_only(), never written
+    read_only: bool,
+    // hash of the file as loaded, to see if someone else changed it since
+    loaded: Option<u64>,
+    // number of entries inserted since it was loaded
+    added: usize,
+    // entries loaded without a stored id, their derived ids are stored when saved
+    derived_ids: usize,
+    // lines skipped by a tolerant load, dropped by rewriting the whole file
+    skipped: usize,
+    // the lock taken by load(), held until the database is dropped so that no other
+    // mesa can change the file between loading and saving it
+    guard: Option<File>,
 }
 
 impl Database {
@@ -75,79 +412,1056 @@ impl Database {
         Database {
             entries: Vec::new(),
             filename: filename.to_string(),
+            version: None,
+            tolerant: false,
+            force: false,
+            broken: false,
+            edited: false,
+            read_only: false,
+            loaded: None,
+            added: 0,
+            derived_ids: 0,
+            skipped: 0,
+            guard: None,
         }
     }
 
-    pub fn load(&mut self) -> Result<(), String> {
-        // no error if file doesn't exist
-        if !std::path::Path::new(&self.filename).exists() {
+    // load a database that is not ours to change, without locking it.
+    // An empty file is an empty database, which is what git gives us for a new file
+    pub fn read_only(filename: &str) -> Result<Database, String> {
+        let mut db = Database::new(filename);
+        db.read_only = true;
+        if let Some(content) = db.read()?.filter(|c| !c.is_empty()) {
+            let (version, entries, _, _) = db.parse(&content)
+                .map_err(|e| format!("Failed loading {}: {}", filename, e))?;
+            db.entries = entries;
+            db.version = Some(version);
+        }
+        Ok(db)
+    }
+
+    pub fn filename(&self) -> &str {
+        &self.filename
+    }
+
+    // another database to compare with, only ever read
+    pub fn open_baseline(filename: &str) -> Result<Database, String> {
+        if !Path::new(filename).exists() {
+            return Err(format!("Baseline database {} does not exist", filename));
+        }
+        Database::read_only(filename)
+    }
+
+    // advisory lock shared by all mesa processes using this database, held until unlocked or dropped.
+    // This is a separate file since saving replaces the database file
+    fn lock(&mut self) -> Result<(), String> {
+        if self.guard.is_some() {
             return Ok(());
         }
+        let filename = format!("{}.lock", self.filename);
+        let file = OpenOptions::new().create(true).truncate(false).write(true).open(&filename)
+            .map_err(|e| format!("Failed to open lock file {}: {}", filename, e))?;
+        match file.try_lock() {
+            Ok(()) => (),
+            Err(TryLockError::WouldBlock) => {
+                eprintln!("Waiting for another mesa to finish with {}", self.filename);
+                file.lock().map_err(|e| format!("Failed to lock {}: {}", filename, e))?;
+            },
+            Err(TryLockError::Error(e)) => return Err(format!("Failed to lock {}: {}", filename, e)),
+        }
+        self.guard = Some(file);
+        Ok(())
+    }
+
+    // let others use the database, for when it will not be saved
+    pub fn unlock(&mut self) {
+        self.guard = None;
+    }
+
+    fn read(&self) -> Result<Option<String>, String> {
+        if !Path::new(&self.filename).exists() {
+            return Ok(None);
+        }
+        std::fs::read_to_string(&self.filename)
+            .map(Some)
+            .map_err(|e| format!("Failed to read database file: {}", e))
+    }
 
-        let content = std::fs::read_to_string(&self.filename)
-            .map_err(|e| format!("Failed to read database file: {}", e))?;
+    // returns the version of the file, its entries sorted and how many of them had no stored id
+    fn parse(&self, content: &str) -> Result<(String, Vec<Entry>, usize, usize), String> {
         let mut lines = content.lines();
 
         // See if the header is correct
         let header = lines.next().ok_or("Error reading database header".to_string())?;
-        if header != DB_HEADER {
-            return Err(format!("Unsupported database version: {}", header));
-        }
+        let version = header.strip_prefix(DB_HEADER)
+            .filter(|v| *v == DB_VERSION || *v == DB_VERSION_1_2)
+            .ok_or(format!("Unsupported database version: {}", header))?;
 
-        // Read each line
-        self.entries = lines
-            .map(|line| line.parse::<Entry>())
-            .collect::<Result<Vec<Entry>, _>>()
-            .map_err(|e| format!("Failed to parse entry: {}", e))?;
+        // Read each line, the header is line 1
+        let mut entries = Vec::new();
+        let mut skipped = 0;
+        let mut derived = 0;
+        for (number, line) in lines.enumerate().map(|(i, line)| (i + 2, line)) {
+            match Entry::parse_stored(line, version) {
+                Ok(mut entry) => {
+                    if entry.id.is_empty() {
+                        entry.id = entry.derive_id();
+                        derived += 1;
+                    }
+                    entries.push(entry);
+                },
+                Err(e) if self.tolerant => {
+                    eprintln!("Skipping line {} of {}: {}", number, self.filename, e);
+                    skipped += 1;
+                },
+                Err(e) => return Err(format!("Failed to parse entry on line {}: {}", number, e)),
+            }
+        }
+        if skipped > 0 {
+            eprintln!("{} bad lines will be dropped when the database is saved", skipped);
+        }
 
         // make sure input is sorted, in case someone edited database by hand
-        self.entries.sort_by_key(|e| e.timestamp);
+        entries.sort_by_key(|e| e.timestamp);
+        Ok((version.to_string(), entries, derived, skipped))
+    }
+
+    // the database stays locked from here until it is dropped or unlocked
+    pub fn load(&mut self) -> Result<(), String> {
+        self.lock()?;
 
+        // no error if file doesn't exist
+        if !Path::new(&self.filename).exists() {
+            return Ok(());
+        }
+
+        // until proven otherwise, saving would lose whatever we failed to read
+        self.broken = true;
+
+        let Some(content) = self.read()? else {
+            return Ok(());
+        };
+        let (version, entries, derived, skipped) = self.parse(&content)?;
+        self.entries = entries;
+        self.version = Some(version);
+        self.derived_ids = derived;
+        self.skipped = skipped;
+        self.loaded = Some(verify::hash(&content));
+        self.added = 0;
+        self.broken = false;
+        self.edited = false;
         Ok(())
     }
 
-    pub fn save(&self) -> Result<(), String> {
-        let mut content = format!("{}\n", DB_HEADER);
+    // run f with the database locked. A lock taken here is released again, one held since
+    // loading is kept
+    fn locked<T>(&mut self, f: impl FnOnce(&mut Database) -> Result<T, String>) -> Result<T, String> {
+        let held = self.guard.is_some();
+        self.lock()?;
+        let result = f(self);
+        if !held {
+            self.unlock();
+        }
+        result
+    }
 
-        for entry in &self.entries {
-            content.push_str(&format!("{}\n", entry.to_string()));
+    // write to a temporary file and rename it, so the database is never half written
+    pub fn save(&mut self) -> Result<(), String> {
+        if self.read_only {
+            return Err(format!("Refusing to write to {} since it was opened read-only", self.filename));
+        }
+        if self.broken && !self.force {
+            return Err(format!("Refusing to overwrite {} since it failed to load", self.filename));
+        }
+        self.locked(Database::save_locked)
+    }
+
+    fn save_locked(&mut self) -> Result<(), String> {
+        // the file was changed without taking the lock, for example by git, add our entries to it.
+        // Edits can't be merged like that, since they may have been made to an old copy
+        let current = self.read()?;
+        let changed = current.as_deref().map(verify::hash) != self.loaded;
+        if !self.broken && changed && self.edited {
+            return Err(format!("Database {} was changed by someone else while editing it, try again", self.filename));
+        }
+        if !self.broken && changed {
+            if let Some(content) = &current {
+                let (_, mut entries, _, _) = self.parse(content)
+                    .map_err(|e| format!("Database changed by someone else and can no longer be read: {}", e))?;
+                entries.extend(self.entries.drain(self.entries.len() - self.added..));
+                entries.sort_by_key(|e| e.timestamp);
+                self.entries = entries;
+            } else {
+                self.entries.drain(..self.entries.len() - self.added);
+            }
+            eprintln!("Database {} was changed by someone else, merged {} new entries into it",
+                      self.filename, self.added);
+        }
+
+        let content = self.to_string();
+
+        // keep the previous version around, in case this one turns out to be wrong
+        if current.is_some() {
+            std::fs::copy(&self.filename, format!("{}.bak", self.filename))
+                .map_err(|e| format!("Failed to back up database file: {}", e))?;
+        }
+
+        let temp = format!("{}.tmp{}", self.filename, std::process::id());
+        let write = || -> std::io::Result<()> {
+            let mut file = File::create(&temp)?;
+            file.write_all(content.as_bytes())?;
+            file.sync_all()?;
+            std::fs::rename(&temp, &self.filename)
+        };
+        write().map_err(|e| {
+            let _ = std::fs::remove_file(&temp);
+            format!("Failed to write to database file: {}", e)
+        })?;
+
+        if let Some(old) = self.version.as_ref().filter(|v| *v != DB_VERSION) {
+            eprintln!("Database {} upgraded from version {} to {}", self.filename, old, DB_VERSION);
+        }
+        if self.derived_ids > 0 {
+            eprintln!("Stored the ids of {} older entries in {}", self.derived_ids, self.filename);
         }
+        self.version = Some(DB_VERSION.to_string());
+        self.loaded = Some(verify::hash(&content));
+        self.added = 0;
+        self.derived_ids = 0;
+        self.skipped = 0;
+        self.broken = false;
+        self.edited = false;
+        Ok(())
+    }
+
+    // write only the new entries at the end of the file, leaving the rest of it untouched.
+    // Falls back to rewriting the file if it is missing, in an older format or has bad lines
+    pub fn append(&mut self) -> Result<(), String> {
+        if self.broken || self.edited || self.read_only || self.skipped > 0 {
+            return self.save();
+        }
+        self.locked(Database::append_locked)
+    }
 
-        std::fs::write(&self.filename, &content)
-            .map_err(|e| format!("Failed to write to database file: {}", e))?;
+    fn append_locked(&mut self) -> Result<(), String> {
+        let header = File::open(&self.filename).ok().and_then(|file| {
+            let mut line = String::new();
+            BufReader::new(file).read_line(&mut line).ok()?;
+            Some(line.trim_end().to_string())
+        });
+        if header != Some(format!("{}{}", DB_HEADER, DB_VERSION)) {
+            return self.save_locked();
+        }
+
+        let mut content = String::new();
+        for entry in &self.entries[self.entries.len() - self.added..] {
+            content.push_str(&format!("{}\n", entry));
+        }
 
+        let append = || -> std::io::Result<()> {
+            let mut file = OpenOptions::new().read(true).append(true).open(&self.filename)?;
+
+            // someone may have edited the file and left out the last newline
+            let mut last = [0u8];
+            file.seek(SeekFrom::End(-1))?;
+            file.read_exact(&mut last)?;
+            if last[0] != b'\n' {
+                file.write_all(b"\n")?;
+            }
+
+            file.write_all(content.as_bytes())?;
+            file.sync_all()
+        };
+        append().map_err(|e| format!("Failed to append to database file: {}", e))?;
+
+        self.added = 0;
         Ok(())
     }
 
-    pub fn insert(&mut self, config: &Config, mean: f64, stddev: f64) -> Result<(), String> {
+    // in the current format, with the ids of all entries stored
+    pub fn is_current(&self) -> bool {
+        self.version.as_ref().is_none_or(|v| v == DB_VERSION) && self.derived_ids == 0
+    }
+
+    pub fn insert(&mut self, config: &Config, measurement: &Measurement) -> Result<(), String> {
+        self.insert_from(config, measurement, Path::new("."))
+    }
+
+    // same as insert(), but the code was checked out in another directory
+    pub fn insert_from(&mut self, config: &Config, measurement: &Measurement, dir: &Path) -> Result<(), String> {
         let timestamp = SystemTime::now()
             .duration_since(UNIX_EPOCH)
             .map_err(|e| format!("Failed to get system time: {}", e))?
             .as_secs();
 
-        let new_entry = Entry {
+        let revision = git::revision(dir, &self.filename);
+
+        let mut new_entry = Entry {
+            id: String::new(),
             timestamp,
+            name: config.name.clone().unwrap_or_default(),
             executable: config.executable.clone(),
             arguments: config.arguments.join(" "),
-            note: config.note.clone(),
-            runs: config.runs,
-            mean,
-            stddev,
+            note: git::expand(&config.note, dir, revision.as_ref()),
+            tags: config.tags.clone(),
+            labels: config.labels.clone(),
+            baseline: false,
+            runs: measurement.runs,
+            mean: measurement.mean,
+            stddev: measurement.stddev,
+            failures: measurement.failures,
+            exit_codes: measurement.exit_codes.clone(),
+            output_hash: measurement.output_hash,
+            mismatches: measurement.mismatches,
+            input: config.input.clone().unwrap_or_default(),
+            cwd: config.cwd.clone().unwrap_or_default(),
+            env: config.env.iter().map(|(k, v)| format!("{}={}", k, v)).collect(),
+            clean_env: config.clean_env,
+            seed: config.seed,
+            params: config.params.iter().map(|(k, v)| format!("{}={}", k, v)).collect(),
+            cpus: config.cpus.clone(),
+            nice: config.nice,
+            cooldown: config.cooldown,
+            noise: measurement.noise,
+            machine: Machine::current(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            git: revision.unwrap_or_default(),
+            extra: Vec::new(),
         };
 
+        // unique even for entries measured in the same second
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.subsec_nanos()).unwrap_or(0);
+        let mut seed = format!("{}|{}|{}", new_entry.derive_id(), nanos, std::process::id());
+        loop {
+            new_entry.id = format!("{:08x}", verify::hash(&seed) as u32);
+            if !self.entries.iter().any(|e| e.id == new_entry.id) {
+                break;
+            }
+            seed.push('+');
+        }
+
         self.entries.push(new_entry);
+        self.added += 1;
         Ok(())
     }
 
+    // position of an entry given on the command line, either by its id or timestamp.
+    // A reference that matches more than one entry is an error, rather than a guess
+    pub fn position(&self, reference: &str) -> Result<usize, String> {
+        let matching = |found: &dyn Fn(&Entry) -> bool| -> Vec<usize> {
+            self.entries.iter().enumerate().filter(|(_, e)| found(e)).map(|(pos, _)| pos).collect()
+        };
+        let mut positions = matching(&|e| e.id == reference);
+        if positions.is_empty() {
+            let timestamp = reference.parse::<u64>().map_err(|_| format!("No entry with id {}", reference))?;
+            positions = matching(&|e| e.timestamp == timestamp);
+        }
+        match positions[..] {
+            [pos] => Ok(pos),
+            [] => Err(format!("No entry with id or timestamp {}", reference)),
+            _ => Err(format!("Ambiguous reference {}, it matches {} entries: {}", reference, positions.len(),
+                             positions.iter().map(|&pos| format!("{} at {}", self.entries[pos].id, self.entries[pos].timestamp))
+                                 .collect::<Vec<_>>().join(", "))),
+        }
+    }
+
+    pub fn find(&self, reference: &str) -> Result<&Entry, String> {
+        self.position(reference).map(|pos| &self.entries[pos])
+    }
+
+    pub fn find_mut(&mut self, reference: &str) -> Result<&mut Entry, String> {
+        let pos = self.position(reference)?;
+        Ok(self.entry_mut(pos))
+    }
+
+    pub fn entry_mut(&mut self, pos: usize) -> &mut Entry {
+        self.edited = true;
+        &mut self.entries[pos]
+    }
+
+    // make the entry the baseline of its program, replacing any other
+    pub fn pin(&mut self, pos: usize) {
+        let key = self.entries[pos].program_key();
+        for other in 0..self.entries.len() {
+            if self.entries[other].baseline && self.entries[other].program_key() == key {
+                self.entry_mut(other).baseline = false;
+            }
+        }
+        self.entry_mut(pos).baseline = true;
+    }
+
+    // an entry to compare with, by id or timestamp, or latest-tag:<tag> for the
+    // newest matching entry with that tag
+    pub fn resolve(&self, reference: &str, cfg: &Config) -> Result<&Entry, String> {
+        let Some(tag) = reference.strip_prefix("latest-tag:") else {
+            return self.find(reference);
+        };
+        self.positions(cfg).into_iter()
+            .map(|pos| &self.entries[pos])
+            .find(|e| e.tags.iter().any(|t| t == tag))
+            .ok_or(format!("No matching entry tagged {}", tag))
+    }
+
+    // what the matching entries are compared with: the one given with --baseline, otherwise
+    // the pinned baseline of the newest matching program, if it has one
+    pub fn baseline(&self, cfg: &Config) -> Result<Option<&Entry>, String> {
+        if let Some(reference) = &cfg.baseline {
+            return self.resolve(reference, cfg).map(Some);
+        }
+        let pinned: Vec<&Entry> = self.positions(cfg).into_iter()
+            .map(|pos| &self.entries[pos])
+            .filter(|e| e.baseline)
+            .collect();
+        let Some(newest) = self.search(&Config { show: 1, offset: 0, ..cfg.clone() }).pop() else {
+            return Ok(None);
+        };
+        Ok(pinned.iter().find(|e| e.program_key() == newest.program_key()).copied())
+    }
+
+    pub fn remove(&mut self, positions: &[usize]) {
+        let positions: HashSet<usize> = positions.iter().copied().collect();
+        let mut index = 0;
+        self.entries.retain(|_| {
+            index += 1;
+            !positions.contains(&(index - 1))
+        });
+        self.edited = true;
+    }
+
     pub fn search(&self, cfg: &Config) -> Vec<&Entry> {
-        let arguments = cfg.arguments.join(" ");
-        self.entries.iter().rev() // rev() so we have them in the order received
-            .filter(|entry| match cfg.filter {
+        self.positions(cfg).into_iter()
+            .skip(cfg.offset)
+            .take(cfg.show)
+            .map(|pos| &self.entries[pos])
+            .collect()
+    }
+
+    // positions of all entries matching the filters, newest first
+    pub fn positions(&self, cfg: &Config) -> Vec<usize> {
+        let executable = normalize_executable(&cfg.executable);
+        let arguments = normalize_arguments(&cfg.arguments.join(" "));
+        let name = cfg.name.as_deref().unwrap_or_default();
+        let machine = cfg.same_machine.then(Machine::current);
+        self.entries.iter().enumerate().rev() // rev() so we have them in the order received
+            .filter(|(_, entry)| match cfg.filter {
                 FilterMode::All => true,
-                FilterMode::Exe => entry.executable == cfg.executable,
-                FilterMode::Exact => entry.executable == cfg.executable && entry.arguments == arguments,
+                FilterMode::Exe => normalize_executable(&entry.executable) == executable,
+                FilterMode::Exact => normalize_executable(&entry.executable) == executable
+                    && normalize_arguments(&entry.arguments) == arguments,
+                FilterMode::Args => normalize_arguments(&entry.arguments) == arguments,
+                FilterMode::Name => entry.name == name,
             })
-            .take(cfg.show)
+            .filter(|(_, entry)| machine.as_ref().is_none_or(|m| m.same(&entry.machine)))
+            .filter(|(_, entry)| cfg.since.is_none_or(|since| entry.timestamp >= since))
+            .filter(|(_, entry)| cfg.until.is_none_or(|until| entry.timestamp < until))
+            .filter(|(_, entry)| cfg.note_contains.as_ref().is_none_or(|text| entry.note.contains(text.as_str())))
+            .filter(|(_, entry)| cfg.args_contains.as_ref().is_none_or(|text| entry.arguments.contains(text.as_str())))
+            .filter(|(_, entry)| cfg.program_regex.as_ref().is_none_or(|regex| regex.is_match(&entry.program())))
+            .filter(|(_, entry)| cfg.has_tags.iter().all(|tag| entry.tags.contains(tag)))
+            .filter(|(_, entry)| cfg.has_labels.iter().all(|(key, value)| match (entry.label(key), value) {
+                (Some(found), Some(value)) => found == value,
+                (found, None) => found.is_some(),
+                (None, _) => false,
+            }))
+            .map(|(pos, _)| pos)
             .collect()
     }
 }
+
+// group entries by program, keeping the order in which each program first appears
+pub fn group<'a>(entries: &[&'a Entry]) -> Vec<Vec<&'a Entry>> {
+    let mut groups: Vec<Vec<&Entry>> = Vec::new();
+    for entry in entries {
+        match groups.iter_mut().find(|g| g[0].program_key() == entry.program_key()) {
+            Some(group) => group.push(entry),
+            None => groups.push(vec![entry]),
+        }
+    }
+    groups
+}
+
+// one entry combining all runs of a group, as if they had been measured together.
+// Anything not shared by the runs, like the git revision, is taken from the first one
+pub fn summarize(entries: &[&Entry]) -> Entry {
+    let mut summary = Entry {
+        id: String::new(), note: String::new(), tags: Vec::new(), labels: Vec::new(), baseline: false,
+        git: Revision::default(), noise: None,
+        ..entries[0].clone()
+    };
+    summary.runs = entries.iter().map(|e| e.runs).sum();
+    summary.failures = entries.iter().map(|e| e.failures).sum();
+    summary.mismatches = entries.iter().map(|e| e.mismatches).sum();
+    summary.exit_codes = entries.iter().flat_map(|e| e.exit_codes.iter().copied()).collect();
+    summary.exit_codes.sort();
+    summary.exit_codes.dedup();
+
+    let runs = summary.runs.max(1) as f64;
+    summary.mean = entries.iter().map(|e| e.runs as f64 * e.mean).sum::<f64>() / runs;
+    let variance = entries.iter()
+        .map(|e| e.runs as f64 * (e.stddev.powi(2) + (e.mean - summary.mean).powi(2)))
+        .sum::<f64>() / runs;
+    summary.stddev = variance.sqrt();
+
+    let min = entries.iter().map(|e| e.mean).fold(f64::INFINITY, f64::min);
+    let max = entries.iter().map(|e| e.mean).fold(f64::NEG_INFINITY, f64::max);
+    summary.note = format!("{} entries, min {:.4}, max {:.4}", entries.len(), min, max);
+    summary
+}
+
+impl fmt::Display for Database {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{}{}", DB_HEADER, DB_VERSION)?;
+        for entry in &self.entries {
+            writeln!(f, "{}", entry)?;
+        }
+        Ok(())
+    }
+}
+
+// three way merge of the entries in two versions of a database with a common base.
+// Entries added on either side are kept, and entries removed or changed on either
+// side are dropped. Entries are compared by how they are written to the file, and an
+// entry changed on both sides is kept once, as we changed it
+pub fn merge(base: &[Entry], ours: &[Entry], theirs: &[Entry]) -> Vec<Entry> {
+    let lines = |entries: &[Entry]| entries.iter().map(|e| e.to_string()).collect::<Vec<_>>();
+    let (base_lines, our_lines, their_lines) = (lines(base), lines(ours), lines(theirs));
+    fn set(lines: &[String]) -> HashSet<&str> {
+        lines.iter().map(String::as_str).collect()
+    }
+    let (in_base, in_ours, in_theirs) = (set(&base_lines), set(&our_lines), set(&their_lines));
+
+    let mut seen = HashSet::new();
+    let mut merged = Vec::new();
+    for (entry, line) in ours.iter().zip(&our_lines).chain(theirs.iter().zip(&their_lines)) {
+        let line = line.as_str();
+        let removed = in_base.contains(line) && !(in_ours.contains(line) && in_theirs.contains(line));
+        if !removed && seen.insert(entry.id.as_str()) {
+            merged.push(entry.clone());
+        }
+    }
+    merged.sort_by_key(|e| e.timestamp);
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a database file in the temp directory, removed with its backup and lock file when dropped
+    struct TempFile(String);
+
+    impl TempFile {
+        fn new(name: &str) -> TempFile {
+            let path = std::env::temp_dir().join(format!("mesa-{}-{}.mesa", name, std::process::id()));
+            TempFile(path.to_string_lossy().to_string())
+        }
+
+        fn path(&self) -> &str {
+            &self.0
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            for suffix in ["", ".bak", ".lock"] {
+                let _ = std::fs::remove_file(format!("{}{}", self.0, suffix));
+            }
+        }
+    }
+
+    #[test]
+    fn test_entry_roundtrip() {
+        let line = "1700000000|python3|fib.py 30|10|0.15|0.01|original code|id=0badc0de";
+        let entry = line.parse::<Entry>().unwrap();
+        assert_eq!(entry.executable, "python3");
+        assert_eq!(entry.arguments, "fib.py 30");
+        assert_eq!(entry.runs, 10);
+        assert_eq!(entry.failures, 0);
+        assert_eq!(entry.to_string(), line);
+    }
+
+    #[test]
+    fn test_entry_id() {
+        // old entries get the same id every time they are read
+        let line = "1700000000|python3|fib.py 30|10|0.15|0.01|original code";
+        let entry = line.parse::<Entry>().unwrap();
+        assert_eq!(entry.id.len(), 8);
+        assert_eq!(entry.id, line.parse::<Entry>().unwrap().id);
+        // and it is stored from then on
+        assert_eq!(entry.to_string(), format!("{}|id={}", line, entry.id));
+
+        let line = "1700000000|python3|fib.py 30|10|0.15|0.01|original code|id=0badc0de";
+        let entry = line.parse::<Entry>().unwrap();
+        assert_eq!(entry.id, "0badc0de");
+        assert_eq!(entry.to_string(), line);
+
+        // entries measured in the same second are told apart
+        let mut db = Database::new("unused.mesa");
+        let config = Config { executable: "sh".to_string(), ..Default::default() };
+        let measurement = Measurement { runs: 3, mean: 0.5, ..Default::default() };
+        db.insert(&config, &measurement).unwrap();
+        db.insert(&config, &measurement).unwrap();
+        assert_ne!(db.entries[0].id, db.entries[1].id);
+        assert_eq!(db.find(&db.entries[0].id.clone()).unwrap().id, db.entries[0].id);
+    }
+
+    #[test]
+    fn test_entry_failures() {
+        let line = "1700000000|./a.out||8|0.5|0|flaky|id=0badc0de|failures=2|codes=1,137";
+        let entry = line.parse::<Entry>().unwrap();
+        assert_eq!(entry.runs, 8);
+        assert_eq!(entry.failures, 2);
+        assert_eq!(entry.exit_codes, vec![1, 137]);
+        assert_eq!(entry.to_string(), line);
+
+        assert!("1700000000|./a.out||8|0.5|0|flaky|bogus".parse::<Entry>().is_err());
+    }
+
+    #[test]
+    fn test_entry_escaping() {
+        let line = "1700000000|sh|-c echo a\\|wc|3|0.5|0|line one\\nline two \\\\o/|id=0badc0de|env=A=x\\|y";
+        let entry = line.parse::<Entry>().unwrap();
+        assert_eq!(entry.arguments, "-c echo a|wc");
+        assert_eq!(entry.note, "line one\nline two \\o/");
+        assert_eq!(entry.env, vec!["A=x|y"]);
+        assert_eq!(entry.to_string(), line);
+
+        assert!("1700000000|sh||3|0.5|0|bad \\x".parse::<Entry>().is_err());
+    }
+
+    #[test]
+    fn test_load_broken() {
+        let file = TempFile::new("broken");
+        let filename = file.path();
+        let content = format!("{}{}\n1700000000|sh||3|0.5|0|\ngarbage\n1700000001|sh||3|0.6|0|\n", DB_HEADER, DB_VERSION);
+        std::fs::write(filename, &content).unwrap();
+
+        let mut db = Database::new(filename);
+        let err = db.load().unwrap_err();
+        assert!(err.contains("line 3"));
+        assert!(db.entries.is_empty());
+        assert!(db.save().is_err());
+        assert_eq!(std::fs::read_to_string(filename).unwrap(), content);
+        drop(db);
+
+        let mut db = Database::new(filename);
+        db.tolerant = true;
+        db.load().unwrap();
+        assert_eq!(db.entries.len(), 2);
+        db.save().unwrap();
+        assert_eq!(std::fs::read_to_string(format!("{}.bak", filename)).unwrap(), content);
+        drop(db);
+
+        // appending a new entry drops the bad line too
+        std::fs::write(filename, &content).unwrap();
+        let mut db = Database::new(filename);
+        db.tolerant = true;
+        db.load().unwrap();
+        let config = Config { executable: "sh".to_string(), ..Default::default() };
+        db.insert(&config, &Measurement { runs: 3, mean: 0.5, ..Default::default() }).unwrap();
+        db.append().unwrap();
+        assert!(!std::fs::read_to_string(filename).unwrap().contains("garbage"));
+        drop(db);
+
+        let mut db = Database::new(filename);
+        db.load().unwrap();
+        assert_eq!(db.entries.len(), 3);
+    }
+
+    #[test]
+    fn test_save_merges() {
+        let file = TempFile::new("merge");
+        let filename = file.path();
+        let config = Config { executable: "sh".to_string(), ..Default::default() };
+        let measurement = Measurement { runs: 3, mean: 0.5, ..Default::default() };
+
+        // changed without taking the lock, like git does
+        let mut db = Database::new(filename);
+        db.load().unwrap();
+        std::fs::write(filename, format!("{}{}\n1700000000|sh||3|0.5|0|\n", DB_HEADER, DB_VERSION)).unwrap();
+        db.insert(&config, &measurement).unwrap();
+        db.save().unwrap();
+        drop(db);
+
+        let mut db = Database::new(filename);
+        db.load().unwrap();
+        assert_eq!(db.entries.len(), 2);
+        assert_eq!(db.entries[0].timestamp, 1700000000);
+    }
+
+    #[test]
+    fn test_lock() {
+        let file = TempFile::new("lock");
+        let filename = file.path().to_string();
+        std::fs::write(&filename, format!("{}{}\n1700000000|sh||3|0.5|0|\n", DB_HEADER, DB_VERSION)).unwrap();
+        let config = Config { executable: "sh".to_string(), ..Default::default() };
+        let measurement = Measurement { runs: 3, mean: 0.5, ..Default::default() };
+
+        // another mesa waits until we are done, so its edits are made to what we saved
+        let mut first = Database::new(&filename);
+        first.load().unwrap();
+        let other = filename.clone();
+        let second = std::thread::spawn(move || {
+            let mut second = Database::new(&other);
+            second.load()?;
+            let pos = second.position("1700000000")?;
+            second.remove(&[pos]);
+            second.save().map(|()| second.entries.len())
+        });
+        std::thread::sleep(std::time::Duration::from_millis(200));
+        first.insert(&config, &measurement).unwrap();
+        first.save().unwrap();
+        drop(first);
+        assert_eq!(second.join().unwrap(), Ok(1));
+
+        // a benchmark only locks while saving, adding its entry to what others saved meanwhile
+        let mut benchmark = Database::new(&filename);
+        benchmark.load().unwrap();
+        benchmark.unlock();
+        let mut other = Database::new(&filename);
+        other.load().unwrap();
+        other.insert(&config, &measurement).unwrap();
+        other.save().unwrap();
+        drop(other);
+        benchmark.insert(&config, &measurement).unwrap();
+        benchmark.append().unwrap();
+        assert!(benchmark.guard.is_none());
+        let mut last = Database::new(&filename);
+        last.load().unwrap();
+        assert_eq!(last.entries.len(), 3);
+    }
+
+    #[test]
+    fn test_append() {
+        let file = TempFile::new("append");
+        let filename = file.path();
+        // hand edited, out of order and without the final newline
+        let content = format!("{}{}\n1700000002|sh||3|0.5|0|\n1700000001|sh||3|0.5|0|", DB_HEADER, DB_VERSION);
+        std::fs::write(filename, &content).unwrap();
+
+        let mut db = Database::new(filename);
+        db.load().unwrap();
+        let config = Config { executable: "sh".to_string(), ..Default::default() };
+        db.insert(&config, &Measurement::default()).unwrap();
+        db.append().unwrap();
+        let added = db.entries.last().unwrap();
+        assert_eq!(std::fs::read_to_string(filename).unwrap(), format!("{}\n{}\n", content, added));
+    }
+
+    #[test]
+    fn test_merge() {
+        let entry = |timestamp: u64, note: &str| Entry {
+            id: timestamp.to_string(), timestamp, executable: "sh".to_string(), note: note.to_string(),
+            ..Default::default()
+        };
+        let base = vec![entry(1, ""), entry(2, ""), entry(3, "")];
+        let ours = vec![entry(1, ""), entry(2, "edited"), entry(3, ""), entry(4, "")];
+        let theirs = vec![entry(2, ""), entry(3, ""), entry(5, ""), entry(4, "")];
+
+        let merged = merge(&base, &ours, &theirs);
+        let merged: Vec<_> = merged.iter().map(|e| (e.timestamp, e.note.as_str())).collect();
+        assert_eq!(merged, vec![(2, "edited"), (3, ""), (4, ""), (5, "")]);
+
+        // changed on both sides, ours wins whichever way it is merged
+        let ours = vec![entry(1, "ours"), entry(2, ""), entry(3, "")];
+        let theirs = vec![entry(1, "theirs"), entry(2, ""), entry(3, "")];
+        let merged = merge(&base, &ours, &theirs);
+        let merged: Vec<_> = merged.iter().map(|e| (e.timestamp, e.note.as_str())).collect();
+        assert_eq!(merged, vec![(1, "ours"), (2, ""), (3, "")]);
+    }
+
+    #[test]
+    fn test_summarize() {
+        let a = Entry { executable: "sh".to_string(), runs: 2, mean: 1.0, stddev: 0.0, ..Default::default() };
+        let b = Entry { executable: "sh".to_string(), runs: 2, mean: 3.0, stddev: 1.0, failures: 1, ..Default::default() };
+        let c = Entry { executable: "ls".to_string(), ..Default::default() };
+
+        let groups = group(&[&a, &c, &b]);
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0].len(), 2);
+
+        let summary = summarize(&groups[0]);
+        assert_eq!(summary.runs, 4);
+        assert_eq!(summary.failures, 1);
+        assert_eq!(summary.mean, 2.0);
+        assert_eq!(summary.stddev, 1.5f64.sqrt());
+        assert_eq!(summary.note, "2 entries, min 1.0000, max 3.0000");
+    }
+
+    #[test]
+    fn test_edit() {
+        let file = TempFile::new("edit");
+        let filename = file.path();
+        let content = format!("{}{}\n1700000001|sh||3|0.5|0|\n1700000002|sh||3|0.6|0|\n", DB_HEADER, DB_VERSION);
+        std::fs::write(filename, &content).unwrap();
+
+        let mut db = Database::new(filename);
+        db.load().unwrap();
+        assert!(!db.is_current());
+        let id = db.entries[1].id.clone();
+        assert_eq!(id.len(), 8);
+        assert_eq!(db.position(&id), Ok(1));
+        assert_eq!(db.position("1700000001"), Ok(0));
+        assert!(db.position("nothing").is_err());
+
+        // never guess which of several entries was meant
+        let mut twins = Database::new("unused.mesa");
+        twins.entries = vec![db.entries[0].clone(), db.entries[0].clone()];
+        twins.entries[1].id = "0badc0de".to_string();
+        assert!(twins.position("1700000001").unwrap_err().contains("Ambiguous"));
+        assert_eq!(twins.position("0badc0de"), Ok(1));
+        twins.entries[0].id = "0badc0de".to_string();
+        assert!(twins.position("0badc0de").unwrap_err().contains("Ambiguous"));
+
+        // the id does not depend on the note
+        db.find_mut(&id).unwrap().note = "slow".to_string();
+        assert_eq!(db.entries[1].derive_id(), id);
+
+        // edits are not merged with changes made by others
+        std::fs::write(filename, format!("{}1700000003|sh||3|0.7|0|\n", content)).unwrap();
+        db.remove(&[0]);
+        assert!(db.save().is_err());
+
+        db.load().unwrap();
+        db.remove(&[0, 2]);
+        db.save().unwrap();
+        drop(db);
+        let mut db = Database::new(filename);
+        db.load().unwrap();
+        assert_eq!(db.entries.len(), 1);
+        assert_eq!(db.entries[0].timestamp, 1700000002);
+        // the derived id was stored
+        assert!(db.is_current());
+        assert_eq!(db.entries[0].id, id);
+    }
+
+    #[test]
+    fn test_retag() {
+        let mut entry = Entry {
+            tags: vec!["lto".to_string(), "pgo".to_string()],
+            labels: vec![("cc".to_string(), "gcc".to_string()), ("opt".to_string(), "O2".to_string())],
+            ..Default::default()
+        };
+        let cfg = Config {
+            tags: vec!["lto".to_string(), "simd".to_string()],
+            labels: vec![("opt".to_string(), "O3".to_string())],
+            untags: vec!["pgo".to_string()],
+            unlabels: vec!["cc".to_string()],
+            ..Default::default()
+        };
+        assert!(entry.retag(&cfg));
+        assert_eq!(entry.tag_list(), "lto simd opt=O3");
+        assert!(!entry.retag(&cfg));
+    }
+
+    #[test]
+    fn test_search() {
+        let mut db = Database::new("unused.mesa");
+        for (timestamp, exe, args, note) in [(100, "python3", "fib.py 30", "lto"), (200, "pypy3", "fib.py 30", ""),
+                                             (300, "python3", "fib.py 20", "lto build"), (400, "a.out", "", "lto")] {
+            db.entries.push(Entry {
+                timestamp, executable: exe.to_string(), arguments: args.to_string(), note: note.to_string(),
+                ..Default::default()
+            });
+        }
+        db.entries[1].name = "fib".to_string();
+        db.entries[2].tags = vec!["lto".to_string(), "pgo".to_string()];
+        db.entries[2].labels = vec![("cc".to_string(), "gcc".to_string())];
+        db.entries[3].labels = vec![("cc".to_string(), "clang".to_string())];
+        let found = |cfg: &Config| db.search(cfg).iter().map(|e| e.timestamp).collect::<Vec<_>>();
+
+        let all = Config { filter: FilterMode::All, ..Default::default() };
+        assert_eq!(found(&all), vec![400, 300, 200, 100]);
+        assert_eq!(found(&Config { offset: 1, show: 2, ..all.clone() }), vec![300, 200]);
+        assert_eq!(found(&Config { since: Some(200), until: Some(400), ..all.clone() }), vec![300, 200]);
+        assert_eq!(found(&Config { note_contains: Some("lto".to_string()), ..all.clone() }), vec![400, 300, 100]);
+        assert_eq!(found(&Config { args_contains: Some("30".to_string()), ..all.clone() }), vec![200, 100]);
+
+        let exe = |executable: &str, filter: FilterMode| Config {
+            executable: executable.to_string(), arguments: vec!["./fib.py".to_string(), "30".to_string()],
+            filter, ..Default::default()
+        };
+        assert_eq!(found(&exe("/usr/bin/python3", FilterMode::Exe)), vec![300, 100]);
+        assert_eq!(found(&exe("python3", FilterMode::Exact)), vec![100]);
+        assert_eq!(found(&exe("./ruby", FilterMode::Args)), vec![200, 100]);
+
+        let named = Config { name: Some("fib".to_string()), filter: FilterMode::Name, ..Default::default() };
+        assert_eq!(found(&named), vec![200]);
+        assert_eq!(db.positions(&named), vec![1]);
+
+        let has_tag = Config { has_tags: vec!["lto".to_string()], ..all.clone() };
+        assert_eq!(found(&has_tag), vec![300]);
+        let has_label = |value: Option<&str>| Config {
+            has_labels: vec![("cc".to_string(), value.map(String::from))], ..all.clone()
+        };
+        assert_eq!(found(&has_label(None)), vec![400, 300]);
+        assert_eq!(found(&has_label(Some("gcc"))), vec![300]);
+
+        let regex = Some(crate::regex::Regex::new("^py.* fib").unwrap());
+        let cfg = Config { program_regex: regex, note_contains: Some("lto".to_string()), ..all.clone() };
+        assert_eq!(found(&cfg), vec![300, 100]);
+    }
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(normalize_executable("./a.out"), "a.out");
+        assert_eq!(normalize_executable("/abs/path/a.out"), "a.out");
+        assert_eq!(normalize_executable("a.out"), "a.out");
+        assert_eq!(normalize_arguments("./x.py ./ -n ../y.py"), "x.py ./ -n ../y.py");
+        assert_eq!(normalize_arguments(""), "");
+    }
+
+    #[test]
+    fn test_baseline() {
+        let mut db = Database::new("unused.mesa");
+        for (timestamp, exe, tag) in [(100, "sh", "v1"), (200, "ls", "v1"), (300, "sh", ""), (400, "sh", "v1"), (500, "sh", "")] {
+            let tags = if tag.is_empty() { vec![] } else { vec![tag.to_string()] };
+            db.entries.push(Entry { id: timestamp.to_string(), timestamp, executable: exe.to_string(), tags, ..Default::default() });
+        }
+        let cfg = Config { executable: "sh".to_string(), ..Default::default() };
+        assert!(db.baseline(&cfg).unwrap().is_none());
+
+        db.pin(0);
+        db.pin(1);
+        assert_eq!(db.baseline(&cfg).unwrap().unwrap().timestamp, 100);
+        db.entries[4].arguments = "-c true".to_string();
+        assert!(db.baseline(&cfg).unwrap().is_none());
+        db.entries[4].arguments.clear();
+        db.pin(2);
+        assert!(!db.entries[0].baseline);
+        assert!(db.entries[1].baseline);
+        assert_eq!(db.baseline(&cfg).unwrap().unwrap().timestamp, 300);
+
+        let latest = |reference: &str| Config { baseline: Some(reference.to_string()), ..cfg.clone() };
+        assert_eq!(db.baseline(&latest("latest-tag:v1")).unwrap().unwrap().timestamp, 400);
+        assert_eq!(db.baseline(&latest("100")).unwrap().unwrap().timestamp, 100);
+        assert!(db.baseline(&latest("latest-tag:v2")).is_err());
+        assert!(db.entries[2].to_string().ends_with("|baseline=1"));
+    }
+
+    #[test]
+    fn test_read_only() {
+        let file = TempFile::new("readonly");
+        let filename = file.path();
+        std::fs::write(filename, "").unwrap();
+
+        let mut db = Database::read_only(filename).unwrap();
+        assert!(db.entries.is_empty());
+        assert!(db.save().is_err());
+        assert!(db.append().is_err());
+        assert!(!Path::new(&format!("{}.lock", filename)).exists());
+    }
+
+    #[test]
+    fn test_open_baseline() {
+        let file = TempFile::new("baseline");
+        let filename = file.path();
+        assert!(Database::open_baseline(filename).is_err());
+
+        let content = format!("{}{}\n1700000000|sh|x|3|0.5|0|main\n", DB_HEADER, DB_VERSION);
+        std::fs::write(filename, &content).unwrap();
+        let db = Database::open_baseline(filename).unwrap();
+        assert_eq!(db.entries.len(), 1);
+        assert_eq!(db.filename(), filename);
+        assert_eq!(db.entries[0].to_string(), format!("1700000000|sh|x|3|0.5|0|main|id={}", db.entries[0].id));
+        drop(db);
+        assert_eq!(std::fs::read_to_string(filename).unwrap(), content);
+    }
+
+    #[test]
+    fn test_entry_versions() {
+        // 1.2 had no escaping and no optional fields, so everything after the sixth | is the note
+        let entry = Entry::parse("1700000000|sh|a\\b|3|0.5|0|build|opt=O3", DB_VERSION_1_2).unwrap();
+        assert_eq!(entry.arguments, "a\\b");
+        assert_eq!(entry.note, "build|opt=O3");
+        assert!(entry.extra.is_empty());
+        assert_eq!(entry.to_string(), format!("1700000000|sh|a\\\\b|3|0.5|0|build\\|opt=O3|id={}", entry.id));
+
+        // unknown fields are kept for newer versions
+        let entry = Entry::parse("1700000000|sh||3|0.5|0|note|future=x", DB_VERSION).unwrap();
+        assert_eq!(entry.extra, vec![("future".to_string(), "x".to_string())]);
+        assert_eq!(entry.to_string(), format!("1700000000|sh||3|0.5|0|note|id={}|future=x", entry.id));
+    }
+
+    #[test]
+    fn test_entry_output() {
+        let line = "1700000000|./a.out||3|0.5|0||id=0badc0de|output=00000000000000ff|mismatches=1";
+        let entry = line.parse::<Entry>().unwrap();
+        assert_eq!(entry.output_hash, Some(255));
+        assert_eq!(entry.mismatches, 1);
+        assert_eq!(entry.to_string(), line);
+    }
+
+    #[test]
+    fn test_entry_name() {
+        let line = "1700000000|./a.out||3|0.5|0||id=0badc0de|name=fib\\|1";
+        let entry = line.parse::<Entry>().unwrap();
+        assert_eq!(entry.name, "fib|1");
+        assert_eq!(entry.program_key(), "name:fib|1");
+        assert_eq!(entry.to_string(), line);
+    }
+
+    #[test]
+    fn test_entry_tags() {
+        let line = "1700000000|./a.out||3|0.5|0||id=0badc0de|tag=lto|tag=pgo|label=compiler=gcc-14";
+        let entry = line.parse::<Entry>().unwrap();
+        assert_eq!(entry.tags, vec!["lto", "pgo"]);
+        assert_eq!(entry.label("compiler"), Some("gcc-14"));
+        assert_eq!(entry.tag_list(), "lto pgo compiler=gcc-14");
+        assert_eq!(entry.to_string(), line);
+        assert!("1700000000|./a.out||3|0.5|0||label=compiler".parse::<Entry>().is_err());
+    }
+
+    #[test]
+    fn test_entry_environment() {
+        let line = "1700000000|./a.out||3|0.5|0||id=0badc0de|input=data.txt|cwd=/tmp|clean_env=1|env=A=1|env=B=2";
+        let entry = line.parse::<Entry>().unwrap();
+        assert_eq!(entry.input, "data.txt");
+        assert_eq!(entry.cwd, "/tmp");
+        assert!(entry.clean_env);
+        assert_eq!(entry.env, vec!["A=1", "B=2"]);
+        assert_eq!(entry.to_string(), line);
+
+        let line = "1700000000|./a.out||3|0.5|0||id=0badc0de|seed=42|param=size=large";
+        let entry = line.parse::<Entry>().unwrap();
+        assert_eq!(entry.seed, Some(42));
+        assert_eq!(entry.params, vec!["size=large"]);
+        assert_eq!(entry.to_string(), line);
+    }
+
+    #[test]
+    fn test_entry_setup() {
+        let line = "1700000000|./a.out||3|0.5|0||id=0badc0de|cpus=2,3|nice=5|cooldown=0.5";
+        let entry = line.parse::<Entry>().unwrap();
+        assert_eq!(entry.cpus, vec![2, 3]);
+        assert_eq!(entry.nice, Some(5));
+        assert_eq!(entry.setup(), "cpus=2,3 nice=5 cooldown=0.5");
+        assert_eq!(entry.to_string(), line);
+
+        let line = "1700000000|./a.out||3|0.5|0||id=0badc0de|noise=0.75";
+        let entry = line.parse::<Entry>().unwrap();
+        assert_eq!(entry.noise, Some(0.75));
+        assert_eq!(entry.to_string(), line);
+    }
+
+    #[test]
+    fn test_entry_machine() {
+        let line = "1700000000|./a.out||3|0.5|0||id=0badc0de|host=ci-1|kernel=6.1.0|cpu=AMD Ryzen 7|cores=8|memory=16777216|mesa=0.2.0";
+        let entry = line.parse::<Entry>().unwrap();
+        assert_eq!(entry.machine.hostname, "ci-1");
+        assert_eq!(entry.machine.cpu, "AMD Ryzen 7");
+        assert_eq!(entry.machine.cores, 8);
+        assert_eq!(entry.version, "0.2.0");
+        assert_eq!(entry.to_string(), line);
+    }
+
+    #[test]
+    fn test_entry_git() {
+        let line = "1700000000|./a.out||3|0.5|0||id=0badc0de|commit=0123456789abcdef|branch=main|dirty=1";
+        let entry = line.parse::<Entry>().unwrap();
+        assert_eq!(entry.git.commit, "0123456789abcdef");
+        assert_eq!(entry.git.branch, "main");
+        assert!(entry.git.dirty);
+        assert_eq!(entry.to_string(), line);
+    }
+}
@@ -0,0 +1,158 @@
+// git metadata for the code being measured, using the local git binary
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Revision {
+    pub commit: String,
+    pub branch: String,
+    pub dirty: bool,
+}
+
+impl Revision {
+    pub fn short(&self) -> &str {
+        &self.commit[..self.commit.len().min(7)]
+    }
+
+    // like git describe --dirty, but only the hash
+    pub fn describe(&self) -> String {
+        if self.commit.is_empty() {
+            String::new()
+        } else if self.dirty {
+            format!("{}-dirty", self.short())
+        } else {
+            self.short().to_string()
+        }
+    }
+}
+
+pub fn git(dir: &Path, args: &[&str]) -> Option<String> {
+    let output = Command::new("git")
+        .args(args)
+        .current_dir(dir)
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    output.status.success().then(|| String::from_utf8_lossy(&output.stdout).trim_end().to_string())
+}
+
+// returns None if dir is not in a git repository. Changes to the database
+// itself are ignored, or recording a measurement would make the tree dirty
+pub fn revision(dir: &Path, database: &str) -> Option<Revision> {
+    let status = ["status", "--porcelain=v2", "--branch", "-z", "--untracked-files=no"];
+    // git refuses to exclude a file outside the repository, but then it can't make it dirty either
+    let exclude = format!(":(exclude,literal){}", database);
+    let output = git(dir, &[&status[..], &["--", ":/", &exclude]].concat())
+        .or_else(|| git(dir, &status))?;
+    parse_status(&output)
+}
+
+// the NUL separated records of git status --porcelain=v2 --branch -z: headers
+// start with #, anything else is a changed file
+fn parse_status(output: &str) -> Option<Revision> {
+    let mut revision = Revision::default();
+    for record in output.split('\0').filter(|r| !r.is_empty()) {
+        if let Some(commit) = record.strip_prefix("# branch.oid ") {
+            revision.commit = commit.to_string();
+        } else if let Some(branch) = record.strip_prefix("# branch.head ") {
+            revision.branch = if branch == "(detached)" { String::new() } else { branch.to_string() };
+        } else if !record.starts_with('#') {
+            revision.dirty = true;
+        }
+    }
+    // no commit yet
+    (!revision.commit.is_empty() && revision.commit != "(initial)").then_some(revision)
+}
+
+// commits in a range such as v1.0..main, oldest first
+pub fn commits(dir: &Path, range: &str) -> Result<Vec<String>, String> {
+    let list = git(dir, &["rev-list", "--reverse", range])
+        .ok_or(format!("Not a valid range of commits: {}", range))?;
+    Ok(list.lines().map(String::from).collect())
+}
+
+// a temporary checkout of a commit, removed when dropped
+pub struct Worktree {
+    repository: PathBuf,
+    pub path: PathBuf,
+}
+
+impl Worktree {
+    pub fn add(repository: &Path, commit: &str) -> Result<Worktree, String> {
+        let name = format!("mesa-{}-{}", std::process::id(), &commit[..commit.len().min(12)]);
+        let path = std::env::temp_dir().join(name);
+        let target = path.to_string_lossy().to_string();
+        git(repository, &["worktree", "add", "--detach", "--force", &target, commit])
+            .ok_or(format!("Failed to check out {} in {}", commit, target))?;
+        Ok(Worktree { repository: repository.to_path_buf(), path })
+    }
+}
+
+impl Drop for Worktree {
+    fn drop(&mut self) {
+        let target = self.path.to_string_lossy().to_string();
+        if git(&self.repository, &["worktree", "remove", "--force", &target]).is_none() {
+            eprintln!("Failed to remove worktree {}", target);
+        }
+    }
+}
+
+// replace {git.commit}, {git.short}, {git.branch}, {git.subject} and {git.dirty} in a note
+pub fn expand(note: &str, dir: &Path, revision: Option<&Revision>) -> String {
+    if !note.contains("{git.") {
+        return note.to_string();
+    }
+
+    let empty = Revision::default();
+    let revision = revision.unwrap_or(&empty);
+    let subject = if note.contains("{git.subject}") && !revision.commit.is_empty() {
+        git(dir, &["log", "-1", "--format=%s", &revision.commit]).unwrap_or_default()
+    } else {
+        String::new()
+    };
+
+    note.replace("{git.commit}", &revision.commit)
+        .replace("{git.short}", revision.short())
+        .replace("{git.branch}", &revision.branch)
+        .replace("{git.subject}", &subject)
+        .replace("{git.dirty}", if revision.dirty { "dirty" } else { "" })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expand() {
+        let revision = Revision {
+            commit: "0123456789abcdef".to_string(),
+            branch: "main".to_string(),
+            dirty: true,
+        };
+        let dir = Path::new(".");
+        assert_eq!(expand("plain note", dir, Some(&revision)), "plain note");
+        assert_eq!(expand("{git.short} on {git.branch} {git.dirty}", dir, Some(&revision)), "0123456 on main dirty");
+        assert_eq!(expand("{git.short}", dir, None), "");
+        assert_eq!(revision.describe(), "0123456-dirty");
+    }
+
+    #[test]
+    fn test_parse_status() {
+        let clean = "# branch.oid 0123456789abcdef\0# branch.head main\0";
+        assert_eq!(parse_status(clean), Some(Revision {
+            commit: "0123456789abcdef".to_string(),
+            branch: "main".to_string(),
+            dirty: false,
+        }));
+
+        // a rename is followed by the original path, and paths are never quoted
+        let renamed = "# branch.oid 0123456789abcdef\0# branch.head (detached)\0\
+                       2 R. N... 100644 100644 100644 1234 1234 R100 new name.txt\0# old name.txt\0";
+        let revision = parse_status(renamed).unwrap();
+        assert_eq!(revision.branch, "");
+        assert!(revision.dirty);
+
+        assert_eq!(parse_status("# branch.oid (initial)\0# branch.head main\0"), None);
+    }
+}
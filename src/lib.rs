@@ -2,7 +2,13 @@ pub mod config;
 pub mod database;
 pub mod output;
 pub mod capture;
+pub mod compare;
+pub mod git;
 pub mod progress;
+pub mod regex;
+pub mod sched;
+pub mod system;
+pub mod verify;
 
 pub use config::*;
 pub use database::*;
@@ -1,27 +1,55 @@
+use std::fs::File;
+use std::io::{self, IsTerminal};
+use std::path::Path;
 use std::process::{Command, Stdio};
-use std::time::Instant;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 use mesa::*;
 
-fn execute_once(config: &Config) -> Result<f64, String> {
+// result of a single run, failure holds the exit code of a failed run
+struct Run {
+    value: f64,
+    failure: Option<i32>,
+    output_hash: u64,
+}
+
+fn execute_once(config: &Config, warmup: bool, index: usize, count: usize) -> Result<Run, String> {
     let mut command = Command::new(&config.executable);
     command.args(&config.arguments)
         .stdout(Stdio::piped())
         .stderr(Stdio::piped())
         ;
 
+    if let Some(filename) = &config.input {
+        let file = File::open(filename).map_err(|e| format!("Failed to open input {}: {}", filename, e))?;
+        command.stdin(file);
+    }
+    if let Some(dir) = &config.cwd {
+        command.current_dir(dir);
+    }
+    if config.clean_env {
+        command.env_clear();
+    }
+    command.envs(config.env.iter().map(|(k, v)| (k, v)));
+
+    // let the program know where in the benchmark it is
+    command.env("MESA_RUN_INDEX", index.to_string())
+        .env("MESA_RUN_KIND", if warmup { "warmup" } else { "benchmark" })
+        .env("MESA_TOTAL_RUNS", count.to_string());
+    if let Some(seed) = config.seed {
+        command.env("MESA_SEED", seed.to_string());
+    }
+    for (name, value) in &config.params {
+        command.env(format!("MESA_PARAM_{}", name), value);
+    }
+    sched::apply(&mut command, &config.cpus, config.nice)?;
+
     let start_time = Instant::now();
     let output = command
         .output().map_err(|e| format!("Error executing program: {}", e))?;
-
-    // value is up here to not include the verbose print time below
-    let value = config.capture.as_ref()
-        .and_then(|c| c.extract(&String::from_utf8_lossy(&output.stdout)))
-        .map(|s| s.trim().parse::<f64>())
-        .transpose()
-        .map_err(|_| "search pattern not found".to_string())?
-        .unwrap_or_else(|| start_time.elapsed().as_secs_f64());
-
+    // elapsed is up here to not include the verbose print time below
+    let elapsed = start_time.elapsed().as_secs_f64();
 
     if config.verbose {
         print!("{}", String::from_utf8_lossy(&output.stdout));
@@ -29,64 +57,624 @@ fn execute_once(config: &Config) -> Result<f64, String> {
     }
 
     let status = output.status;
-    if !status.success() && !config.ignore_failure {
-        Err( format!("Program failed with error code {}", status) )
-    } else {
-        Ok( value )
+    if !status.success() {
+        if !config.ignore_failure {
+            return Err( format!("Program failed with error code {}", status) );
+        }
+        // a failed run has no meaningful value, don't look for one
+        return Ok( Run { value: 0.0, failure: Some(status.code().unwrap_or(-1)), output_hash: 0 } );
     }
+
+    let value = config.capture.as_ref()
+        .and_then(|c| c.extract(&String::from_utf8_lossy(&output.stdout)))
+        .map(|s| s.trim().parse::<f64>())
+        .transpose()
+        .map_err(|_| "search pattern not found".to_string())?
+        .unwrap_or(elapsed);
+
+    Ok( Run { value, failure: None, output_hash: verify::hash_output(&output.stdout) } )
 }
 
-fn execute(config: &Config, count: usize, warmup: bool, prev_mean: Option<f64>, prev_std: Option<f64>) -> Result<(f64, f64), String> {
+fn execute(config: &Config, count: usize, warmup: bool, prev: Option<&Measurement>) -> Result<Measurement, String> {
+    let mut result = Measurement::default();
     if count == 0 {
-        return Ok((0.0, 0.0));
+        return Ok(result);
     }
 
+    // output is compared against the expected file if given, otherwise against the first run
+    let expected = config.expect_output.as_deref().map(verify::hash_file).transpose()?;
+    let check_output = expected.is_some() || config.check_stable_output;
+
     let label = if warmup { "Warmup"} else { "Benchmark" };
     let mut measurements = Vec::with_capacity(count);
-    let mut bar = Progress::new(count, label, config.quiet, prev_mean, prev_std);
-    for _ in 0..count {
+    let mut bar = Progress::new(count, label, config.quiet,
+                                prev.map(|m| m.mean), prev.map(|m| m.stddev));
+    for index in 0..count {
+        if index > 0 && config.cooldown > 0.0 {
+            thread::sleep(Duration::from_secs_f64(config.cooldown));
+        }
         bar.start();
-        let measurement = execute_once(config)?;
-        measurements.push(measurement);
-        bar.stop(measurement);
+        let run = execute_once(config, warmup, index, count)?;
+        if let Some(code) = run.failure {
+            bar.fail();
+            result.failures += 1;
+            if !result.exit_codes.contains(&code) {
+                result.exit_codes.push(code);
+            }
+            if config.max_failures.is_some_and(|max| result.failures > max) {
+                bar.finish(false);
+                return Err(format!("Aborted after {} failed runs", result.failures));
+            }
+        } else {
+            let first = *result.output_hash.get_or_insert(run.output_hash);
+            if check_output && run.output_hash != expected.unwrap_or(first) {
+                if !config.ignore_failure {
+                    bar.finish(false);
+                    return Err(match &config.expect_output {
+                        Some(filename) => format!("Program output differs from {}", filename),
+                        None => "Program output differs between runs".to_string(),
+                    });
+                }
+                result.mismatches += 1;
+            }
+            measurements.push(run.value);
+            bar.stop(run.value);
+        }
     }
     bar.finish(warmup);
 
+    if measurements.is_empty() {
+        return Err(format!("All {} runs failed", count));
+    }
+
     let sum: f64 = measurements.iter().sum();
-    let mean = sum / (count as f64);
-    let variance_sum: f64 = measurements.iter().map(|&d| (d - mean).powi(2)).sum();
-    let std_dev = (variance_sum / (measurements.len() as f64)).sqrt();
-    Ok((mean, std_dev))
+    result.runs = measurements.len();
+    result.mean = sum / (result.runs as f64);
+    let variance_sum: f64 = measurements.iter().map(|&d| (d - result.mean).powi(2)).sum();
+    result.stddev = (variance_sum / (result.runs as f64)).sqrt();
+    result.exit_codes.sort();
+    // the output may hold the captured value or anything else that changes between runs,
+    // so its hash is only worth keeping when asked to check it
+    if !check_output {
+        result.output_hash = None;
+    }
+    Ok(result)
 }
 
+// look for anything that could disturb the measurements, and possibly wait for it to go away
+fn check_noise(config: &Config) -> Option<f64> {
+    if !config.noise_check {
+        return None;
+    }
 
-fn main() -> Result<(), String> {
-    let config = Config::from_env().map_err(|e| {
-        Config::help();
-        e
-    })?;
+    let interval = Duration::from_millis(250);
+    let mut noise = system::Noise::sample(interval)?;
+    if let Some(threshold) = config.wait_for_idle {
+        while noise.score() > threshold {
+            if !config.quiet {
+                eprintln!("Waiting for the system to become idle, noise is {:.2}", noise.score());
+            }
+            thread::sleep(Duration::from_secs(5));
+            noise = system::Noise::sample(interval)?;
+        }
+    }
 
-    let mut db = Database::new(&config.database);
-    db.load().unwrap_or_else( |err| eprintln!("Failed loading database: {:?}", err) );
+    if !config.quiet {
+        for warning in noise.warnings() {
+            eprintln!("Warning: {}", warning);
+        }
+    }
+    Some(noise.score())
+}
 
+// warmup and benchmark the program
+fn benchmark(config: &Config) -> Result<Measurement, String> {
+    let noise = check_noise(config);
 
     // warmup round:
-    let (warmup_mean, warmup_std) = execute(&config, config.warmups, true, None, None)?;
+    let warmup = execute(config, config.warmups, true, None)?;
     if config.warmups > 0 && config.verbose {
         eprintln!("After {} warmup rounds: mean={:3.3}s stddev={:3.3}",
-                 config.warmups, warmup_mean, warmup_std);
+                 config.warmups, warmup.mean, warmup.stddev);
+    }
+
+    let prev = (config.warmups > 0).then_some(&warmup);
+    let mut measurement = execute(config, config.runs, false, prev)?;
+    measurement.noise = noise;
+    if measurement.failures > 0 {
+        eprintln!("{} of {} runs failed (exit codes {:?})",
+                  measurement.failures, config.runs, measurement.exit_codes);
+    }
+    if measurement.mismatches > 0 {
+        eprintln!("{} of {} runs produced unexpected output", measurement.mismatches, config.runs);
     }
+    Ok(measurement)
+}
 
-    let (prev_mean, prev_std) = if config.warmups > 0 { (Some(warmup_mean), Some(warmup_std)) } else { (None, None) };
-    let (mean, std_dev) = execute(&config, config.runs, false, prev_mean, prev_std)?;
+// exit code when --fail-if-slower finds a regression
+const EXIT_SLOWER: i32 = 3;
 
-    // record the outcome
-    db.insert(&config, mean, std_dev)?;
-    if !config.dry_run {
-        db.save()?;
+// returns the exit code
+fn run(config: &Config, db: &mut Database, other: Option<&Database>) -> Result<i32, String> {
+    // decided before running, so the new entry is never its own reference
+    let reference = match config.fail_if_slower {
+        Some(_) => compared_with(config, db, other)?.map(|(e, _)| e).or(db.search(config).first().copied()).cloned(),
+        None => None,
+    };
+
+    let measurement = benchmark(config)?;
+    let check = match (config.fail_if_slower, &reference) {
+        (Some(limit), Some(reference)) => Some(check_regression(config, &measurement, reference, limit)),
+        _ => None,
+    };
+    let code = check.as_ref().map_or(0, |(code, _)| *code);
+
+    // record the outcome, but a regression must not become the reference of the next run
+    db.insert(config, &measurement)?;
+    if !config.dry_run && code == 0 {
+        db.append()?;
     }
 
     // show me what you get
-    let search_result = db.search(&config);
-    write_output(&config.output, search_result, config.reverse)
+    write_rows(&config.output, matching(config, db, other), compared_with(config, db, other)?, config.reverse)?;
+
+    match check {
+        Some((code, verdict)) => {
+            eprintln!("{}", verdict);
+            if code != 0 && !config.dry_run {
+                eprintln!("The entry was not recorded, so the next run is compared with the same reference");
+            }
+        },
+        None if config.fail_if_slower.is_some() => eprintln!("Nothing to compare with, skipping the regression check"),
+        None => (),
+    }
+    Ok(code)
+}
+
+// the exit code and verdict of --fail-if-slower
+fn check_regression(config: &Config, measurement: &Measurement, reference: &Entry, limit: compare::Limit) -> (i32, String) {
+    let (sample, base) = (compare::Sample::from(measurement), compare::Sample::from(reference));
+    let (_, z) = compare::compare(&sample, &base, config.reverse);
+    let outcome = compare::regression(&sample, &base, limit, config.reverse);
+    let change = (sample.mean - reference.mean) / reference.mean.abs().max(f64::MIN_POSITIVE) * 100.0;
+    let verdict = match outcome {
+        compare::Regression::Passed => "passed",
+        compare::Regression::WithinLimit => "passed, slower but within the limit",
+        compare::Regression::WithinNoise => "passed, the difference is within noise",
+        compare::Regression::Failed => "FAILED, slower than allowed",
+    };
+    let message = format!("Regression check against {}: mean={:.4} vs {:.4} ({:+.2}%, {}): {}",
+                          reference.id, sample.mean, reference.mean, change, standard_errors(z), verdict);
+    (if outcome == compare::Regression::Failed { EXIT_SLOWER } else { 0 }, message)
+}
+
+// without a standard deviation on either side any difference is infinitely many standard errors
+fn standard_errors(z: f64) -> String {
+    if z.is_finite() { format!("{:.1} standard errors", z) } else { "no standard deviation".to_string() }
+}
+
+// build and benchmark each commit in its own worktree
+fn history(config: &Config, db: &mut Database) -> Result<(), String> {
+    let commits = git::commits(Path::new("."), &config.commits)?;
+    if commits.is_empty() {
+        return Err(format!("No commits in {}", config.commits));
+    }
+
+    let mut recorded = 0;
+    for (i, commit) in commits.iter().enumerate() {
+        let short = &commit[..commit.len().min(7)];
+        eprintln!("Commit {} ({}/{})", short, i + 1, commits.len());
+
+        let worktree = git::Worktree::add(Path::new("."), commit)?;
+        if let Some(build) = &config.build {
+            let output = Command::new("sh").arg("-c").arg(build)
+                .current_dir(&worktree.path)
+                .output()
+                .map_err(|e| format!("Error executing build: {}", e))?;
+            if config.verbose || !output.status.success() {
+                print!("{}", String::from_utf8_lossy(&output.stdout));
+                eprint!("{}", String::from_utf8_lossy(&output.stderr));
+            }
+            if !output.status.success() {
+                eprintln!("Build failed for {}, skipping it", short);
+                continue;
+            }
+        }
+
+        // the program and its working directory are relative to the checkout
+        let mut cfg = config.clone();
+        let cwd = worktree.path.join(config.cwd.as_deref().unwrap_or(""));
+        if config.executable.contains('/') {
+            cfg.executable = cwd.join(&config.executable).to_string_lossy().to_string();
+        }
+        cfg.cwd = Some(cwd.to_string_lossy().to_string());
+
+        match benchmark(&cfg) {
+            Ok(measurement) => {
+                // record the command as given, not the temporary paths
+                db.insert_from(config, &measurement, &worktree.path)?;
+                recorded += 1;
+            },
+            Err(e) => eprintln!("Benchmark failed for {}, skipping it: {}", short, e),
+        }
+    }
+
+    if recorded == 0 {
+        return Err("None of the commits could be benchmarked".to_string());
+    }
+    if !config.dry_run {
+        db.append()?;
+    }
+
+    // the trend, newest commit first
+    let trend: Vec<&Entry> = db.entries.iter().rev().take(recorded).collect();
+    write_output(&config.output, trend, None, config.reverse)
+}
+
+fn migrate(db: &mut Database) -> Result<(), String> {
+    if db.is_current() {
+        eprintln!("Database is already in the current format");
+        return Ok(());
+    }
+    db.save()
+}
+
+// the entries as recorded, without running anything
+fn show(config: &Config, db: &Database, other: Option<&Database>) -> Result<(), String> {
+    write_rows(&config.output, matching(config, db, other), compared_with(config, db, other)?, config.reverse)
+}
+
+// matching entries of our database and the baseline database, up to --show of each, newest first
+fn matching<'a>(config: &Config, db: &'a Database, other: Option<&'a Database>) -> Vec<Row<'a>> {
+    let mut rows: Vec<Row> = db.search(config).into_iter().map(|e| (e, None)).collect();
+    if let Some(other) = other {
+        rows.extend(other.search(config).into_iter().map(|e| (e, Some(other.filename()))));
+        rows.sort_by_key(|(e, _)| std::cmp::Reverse(e.timestamp));
+    }
+    rows
+}
+
+// what the matching entries are compared with. The baseline database holds the reference numbers,
+// so unless we have pinned a baseline its pinned baseline, or else its newest matching entry, is used
+fn compared_with<'a>(config: &Config, db: &'a Database, other: Option<&'a Database>) -> Result<Option<Row<'a>>, String> {
+    let ours = |e| (e, None);
+    let Some(other) = other else {
+        return Ok(db.baseline(config)?.map(ours));
+    };
+    let theirs = |e| (e, Some(other.filename()));
+    if let Some(reference) = &config.baseline {
+        return match db.resolve(reference, config) {
+            Ok(e) => Ok(Some(ours(e))),
+            Err(_) => other.resolve(reference, config).map(|e| Some(theirs(e))),
+        };
+    }
+    if let Some(pinned) = db.baseline(config)? {
+        return Ok(Some(ours(pinned)));
+    }
+    Ok(other.baseline(config)?.or(other.search(config).first().copied()).map(theirs))
+}
+
+// one row per program, either its latest entry or all of its entries combined
+fn programs(config: &Config, db: &Database, combine: bool) -> Result<(), String> {
+    let all = Config { show: usize::MAX, offset: 0, ..config.clone() };
+    let groups = database::group(&db.search(&all));
+    let rows: Vec<Entry> = groups.iter().skip(config.offset).take(config.show)
+        .map(|g| if combine { database::summarize(g) } else { g[0].clone() })
+        .collect();
+    write_summary(&config.output, rows.iter().collect(), config.reverse)
+}
+
+// ask before changes that can't easily be undone, --yes answers for scripts
+fn confirm(config: &Config, question: &str) -> Result<bool, String> {
+    if config.yes {
+        return Ok(true);
+    }
+    if !io::stdin().is_terminal() {
+        return Err("Not asking for confirmation without a terminal, use --yes".to_string());
+    }
+    eprint!("{} [y/N] ", question);
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer).map_err(|e| format!("Failed to read the answer: {}", e))?;
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
+
+// remove entries from the database, showing what is removed first
+fn remove(config: &Config, db: &mut Database, positions: Vec<usize>) -> Result<(), String> {
+    if positions.is_empty() {
+        eprintln!("Nothing to remove");
+        return Ok(());
+    }
+
+    let removed: Vec<&Entry> = positions.iter().rev().map(|&pos| &db.entries[pos]).collect();
+    let action = if config.dry_run { "Would remove" } else { "Removing" };
+    eprintln!("{} {} of {} entries:", action, removed.len(), db.entries.len());
+    write_summary(&config.output, removed, config.reverse)?;
+    if config.dry_run {
+        return Ok(());
+    }
+    if !confirm(config, "Remove them?")? {
+        eprintln!("Nothing removed");
+        return Ok(());
+    }
+
+    db.remove(&positions);
+    db.save()
+}
+
+fn delete(config: &Config, db: &mut Database) -> Result<(), String> {
+    let mut positions = config.operands.iter()
+        .map(|id| db.position(id))
+        .collect::<Result<Vec<_>, _>>()?;
+    positions.sort();
+    positions.dedup();
+    remove(config, db, positions)
+}
+
+fn note(config: &Config, db: &mut Database) -> Result<(), String> {
+    let (id, text) = (&config.operands[0], &config.operands[1]);
+    let entry = db.find_mut(id)?;
+    let action = if config.dry_run { "Would change" } else { "Changing" };
+    eprintln!("{} note of {} from \"{}\" to \"{}\"", action, entry.id, entry.note, text);
+    entry.note = text.to_string();
+    if config.dry_run {
+        return Ok(());
+    }
+    db.save()
+}
+
+// add or remove tags and labels of entries, showing the result first
+fn tag(config: &Config, db: &mut Database) -> Result<(), String> {
+    let mut positions = config.operands.iter()
+        .map(|id| db.position(id))
+        .collect::<Result<Vec<_>, _>>()?;
+    positions.sort();
+    positions.dedup();
+
+    let mut changed = Vec::new();
+    for pos in positions.into_iter().rev() {
+        let mut entry = db.entries[pos].clone();
+        if entry.retag(config) {
+            changed.push((pos, entry));
+        }
+    }
+    if changed.is_empty() {
+        eprintln!("Nothing to change");
+        return Ok(());
+    }
+
+    let action = if config.dry_run { "Would retag" } else { "Retagging" };
+    eprintln!("{} {} of {} entries:", action, changed.len(), db.entries.len());
+    write_summary(&config.output, changed.iter().map(|(_, e)| e).collect(), config.reverse)?;
+    if config.dry_run {
+        return Ok(());
+    }
+
+    for (pos, entry) in changed {
+        *db.entry_mut(pos) = entry;
+    }
+    db.save()
+}
+
+// give entries a new name, either all with the old name or those of a program
+fn rename(config: &Config, db: &mut Database) -> Result<(), String> {
+    let (positions, name) = match &config.operands[..] {
+        [old, new] => {
+            let positions = db.entries.iter().enumerate().rev()
+                .filter(|(_, e)| e.name == *old)
+                .map(|(pos, _)| pos)
+                .collect::<Vec<_>>();
+            (positions, new.to_string())
+        },
+        _ => (db.positions(config), config.name.clone().unwrap_or_default()),
+    };
+    if positions.is_empty() {
+        eprintln!("Nothing to rename");
+        return Ok(());
+    }
+
+    let action = if config.dry_run { "Would rename" } else { "Renaming" };
+    eprintln!("{} {} of {} entries to {}:", action, positions.len(), db.entries.len(), name);
+    write_summary(&config.output, positions.iter().map(|&pos| &db.entries[pos]).collect(), config.reverse)?;
+    if config.dry_run {
+        return Ok(());
+    }
+
+    for pos in positions {
+        db.entry_mut(pos).name = name.clone();
+    }
+    db.save()
+}
+
+// pin an entry as the baseline of its program, or unpin it
+fn baseline(config: &Config, db: &mut Database) -> Result<(), String> {
+    let pos = db.position(&config.operands[1])?;
+    let entry = &db.entries[pos];
+    let pin = config.operands[0] == "set";
+    if entry.baseline == pin {
+        eprintln!("Entry {} is {} the baseline", entry.id, if pin { "already" } else { "not" });
+        return Ok(());
+    }
+
+    let action = match (pin, config.dry_run) {
+        (true, false) => "Pinning",
+        (true, true) => "Would pin",
+        (false, false) => "Unpinning",
+        (false, true) => "Would unpin",
+    };
+    eprintln!("{} {} as the baseline of {}", action, entry.id, entry.program());
+    if config.dry_run {
+        return Ok(());
+    }
+
+    if pin {
+        db.pin(pos);
+    } else {
+        db.entry_mut(pos).baseline = false;
+    }
+    db.save()
+}
+
+// remove entries older than given, except for the newest ones to keep
+fn prune(config: &Config, db: &mut Database) -> Result<(), String> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_err(|e| format!("Failed to get system time: {}", e))?
+        .as_secs();
+    let cutoff = config.older_than.map(|age| now.saturating_sub(age));
+
+    let mut kept: Vec<(String, usize)> = Vec::new();
+    let mut positions = Vec::new();
+    for (pos, entry) in db.entries.iter().enumerate().rev() {
+        let key = if config.per_program { entry.program_key() } else { String::new() };
+        let count = match kept.iter_mut().find(|(k, _)| *k == key) {
+            Some((_, count)) => count,
+            None => {
+                kept.push((key, 0));
+                &mut kept.last_mut().unwrap().1
+            },
+        };
+        let old = cutoff.is_none_or(|cutoff| entry.timestamp < cutoff);
+        let newest = config.keep_last.is_some_and(|keep| *count < keep);
+        if old && !newest {
+            positions.push(pos);
+        } else {
+            *count += 1;
+        }
+    }
+    positions.reverse();
+    remove(config, db, positions)
+}
+
+// called by git with the three versions of a database, the result replaces ours
+fn merge_driver(config: &Config) -> Result<(), String> {
+    let [base, ours, theirs] = &config.operands[..] else {
+        return Err("The merge driver needs three files".to_string());
+    };
+    let base = Database::read_only(base)?;
+    let mut merged = Database::read_only(ours)?;
+    let theirs = Database::read_only(theirs)?;
+
+    merged.entries = database::merge(&base.entries, &merged.entries, &theirs.entries);
+    std::fs::write(ours, merged.to_string()).map_err(|e| format!("Failed to write {}: {}", ours, e))
+}
+
+// exit codes understood by git bisect run
+const BISECT_GOOD: i32 = 0;
+const BISECT_BAD: i32 = 1;
+const BISECT_SKIP: i32 = 125;
+const BISECT_ABORT: i32 = 128;
+
+// benchmark this checkout and tell git bisect if it is slow, nothing is recorded
+// since changing the database would make the checkout dirty. A checkout that can't
+// be benchmarked is skipped, any other error aborts the bisect
+fn bisect(config: &Config, db: &Database) -> Result<i32, String> {
+    let (reference, name) = match (&config.baseline, config.threshold) {
+        (Some(baseline), _) => {
+            let entry = db.resolve(baseline, config)?;
+            (compare::Sample::from(entry), format!("baseline {}", baseline))
+        },
+        (None, Some(threshold)) => (compare::Sample::exact(threshold), "threshold".to_string()),
+        (None, None) => return Err("Nothing to compare with".to_string()),
+    };
+
+    let measurement = match benchmark(config) {
+        Ok(measurement) => measurement,
+        Err(e) => {
+            eprintln!("Benchmark failed, skipping this commit: {}", e);
+            return Ok(BISECT_SKIP);
+        },
+    };
+    let sample = compare::Sample::from(&measurement);
+    let (verdict, z) = compare::compare(&sample, &reference, config.reverse);
+    let noisy = measurement.noise.is_some_and(|n| n > system::NOISY);
+
+    let code = match verdict {
+        _ if noisy => BISECT_SKIP,
+        compare::Verdict::Slower => BISECT_BAD,
+        compare::Verdict::Faster | compare::Verdict::Same => BISECT_GOOD,
+        // faster, but not by much. Either way it is not slower
+        compare::Verdict::Inconclusive if z < 0.0 => BISECT_GOOD,
+        compare::Verdict::Inconclusive => BISECT_SKIP,
+    };
+    let outcome = match code {
+        BISECT_GOOD => "good",
+        BISECT_BAD => "bad",
+        _ if noisy => "skip, system is busy",
+        _ => "skip, difference is within noise",
+    };
+    eprintln!("mean={:.4} stddev={:.4} vs {} mean={:.4} stddev={:.4} ({}): {}",
+              sample.mean, sample.stddev, name, reference.mean, reference.stddev, standard_errors(z), outcome);
+    Ok(code)
+}
+
+fn open_database(config: &Config) -> Result<Database, String> {
+    let mut db = Database::new(&config.database);
+    db.tolerant = config.tolerant;
+    db.force = config.force;
+    if let Err(err) = db.load() {
+        if !config.force {
+            return Err(format!("Failed loading database: {}. Use --tolerant to skip bad entries \
+                               or --force to overwrite the database", err));
+        }
+        eprintln!("Failed loading database, it will be overwritten: {}", err);
+    }
+    // only commands that edit entries keep the database locked until they are done. New
+    // entries are added to whatever others saved in the meantime, so benchmarks run in parallel
+    if config.action.is_query() || config.action.needs_program() || config.dry_run {
+        db.unlock();
+    }
+    Ok(db)
+}
+
+fn main() -> Result<(), String> {
+    // git bisect must stop on a mistake in the command line or database,
+    // rather than count every commit as bad
+    let config = match Config::from_env() {
+        Ok(config) => config,
+        Err(e) if std::env::args().nth(1).as_deref() == Some("bisect") => {
+            eprintln!("Error: {}", e);
+            std::process::exit(BISECT_ABORT);
+        },
+        Err(e) => {
+            Config::help();
+            return Err(e);
+        },
+    };
+
+    let code = match dispatch(&config) {
+        Ok(code) => code,
+        Err(e) if config.action == Action::Bisect => {
+            eprintln!("Error: {}", e);
+            BISECT_ABORT
+        },
+        Err(e) => return Err(e),
+    };
+    if code != 0 {
+        std::process::exit(code);
+    }
+    Ok(())
+}
+
+// run the command, returns the exit code
+fn dispatch(config: &Config) -> Result<i32, String> {
+    let done = |result: Result<(), String>| result.map(|()| 0);
+    // only ever read, so it can be the committed database of another branch
+    let other = || config.baseline_db.as_deref().map(Database::open_baseline).transpose();
+    match config.action {
+        Action::Run => run(config, &mut open_database(config)?, other()?.as_ref()),
+        Action::Bisect => bisect(config, &open_database(config)?),
+        Action::History => done(history(config, &mut open_database(config)?)),
+        Action::Migrate => done(migrate(&mut open_database(config)?)),
+        // works on the files given by git, not on the database
+        Action::MergeDriver => done(merge_driver(config)),
+        Action::Show | Action::List => done(show(config, &open_database(config)?, other()?.as_ref())),
+        Action::Stats => done(programs(config, &open_database(config)?, true)),
+        Action::Programs => done(programs(config, &open_database(config)?, false)),
+        Action::Delete => done(delete(config, &mut open_database(config)?)),
+        Action::Note => done(note(config, &mut open_database(config)?)),
+        Action::Tag => done(tag(config, &mut open_database(config)?)),
+        Action::Prune => done(prune(config, &mut open_database(config)?)),
+        Action::Rename => done(rename(config, &mut open_database(config)?)),
+        Action::Baseline => done(baseline(config, &mut open_database(config)?)),
+    }
 }
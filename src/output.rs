@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use std::time::{SystemTime, UNIX_EPOCH};
 use std::path::Path;
 use std::ffi::OsStr;
@@ -6,8 +7,37 @@ use std::fs::{File};
 
 use crate::*;
 
-pub fn write_output(output: &str, measurements: Vec<&Entry>, reverse: bool) -> Result<(), String> {
-    if measurements.is_empty() {
+// an entry and the database it was read from, if that is not ours
+pub type Row<'a> = (&'a Entry, Option<&'a str>);
+
+// what the Change (%) column of the table is relative to
+enum Reference<'a> {
+    First,
+    Baseline(Row<'a>),
+    Nothing,
+}
+
+// changes are shown relative to the baseline if given, otherwise to the first entry
+pub fn write_output<'a>(output: &str, measurements: Vec<&'a Entry>, baseline: Option<&'a Entry>,
+                        reverse: bool) -> Result<(), String> {
+    write_rows(output, measurements.into_iter().map(|e| (e, None)).collect(), baseline.map(|e| (e, None)), reverse)
+}
+
+// like write_output, for entries from more than one database
+pub fn write_rows<'a>(output: &str, rows: Vec<Row<'a>>, baseline: Option<Row<'a>>,
+                      reverse: bool) -> Result<(), String> {
+    let reference = baseline.map_or(Reference::First, Reference::Baseline);
+    write_entries(output, rows, reference, reverse)
+}
+
+// rows that summarize different programs, so there are no changes to show
+pub fn write_summary(output: &str, measurements: Vec<&Entry>, reverse: bool) -> Result<(), String> {
+    write_entries(output, measurements.into_iter().map(|e| (e, None)).collect(), Reference::Nothing, reverse)
+}
+
+fn write_entries<'a>(output: &str, mut rows: Vec<Row<'a>>, reference: Reference<'a>,
+                     reverse: bool) -> Result<(), String> {
+    if rows.is_empty() {
         eprintln!("Nothing to output...");
         return Ok(());
     }
@@ -26,20 +56,34 @@ pub fn write_output(output: &str, measurements: Vec<&Entry>, reverse: bool) -> R
     let writer = if terminal {
         Box::new(io::stdout()) as Box<dyn Write>
     } else {
-        let file = File::create(&path).map_err(|e| format!("Unable to create output file: {:?}", e))?;
+        let file = File::create(path).map_err(|e| format!("Unable to create output file: {:?}", e))?;
         Box::new(file) as Box<dyn Write>
     };
 
     match ext {
-        "" | "txt" | "table" => output_table(writer, terminal, measurements, reverse),
-        "csv" => output_csv(writer, measurements),
-        "json" => output_json(writer, measurements),
-        "xml" => output_xml(writer, measurements),
+        "" | "txt" | "table" => {
+            // the table shows the baseline even if it is older than the rest
+            let pinned = matches!(reference, Reference::Baseline(_));
+            let reference = match reference {
+                Reference::Baseline(b) => Some(rows.iter().position(|r| std::ptr::eq(r.0, b.0)).unwrap_or_else(|| {
+                    rows.push(b);
+                    rows.len() - 1
+                })),
+                Reference::First => Some(0),
+                Reference::Nothing => None,
+            };
+            output_table(writer, terminal, rows, reference, pinned, reverse)
+        }
+        "csv" => output_csv(writer, rows),
+        "json" => output_json(writer, rows),
+        "xml" => output_xml(writer, rows),
         _ => return Err(format!("Unknown output format for {}", output)),
-    }.map_err(|_| format!("write output failed"))
+    }.map_err(|_| "write output failed".to_string())
 }
 
-fn output_table(mut wr: Box<dyn Write>, color: bool, measurements: Vec<&Entry>, reverse: bool) -> Result<(), io::Error> {
+fn output_table(mut wr: Box<dyn Write>, color: bool, rows: Vec<Row>,
+                reference: Option<usize>, pinned: bool, reverse: bool) -> Result<(), io::Error> {
+    let measurements: Vec<&Entry> = rows.iter().map(|(e, _)| *e).collect();
     let now = SystemTime::now()
         .duration_since(UNIX_EPOCH)
         .expect("save yourself, end of time is here") // no point recovering from this :(
@@ -48,18 +92,29 @@ fn output_table(mut wr: Box<dyn Write>, color: bool, measurements: Vec<&Entry>,
     // figure out what data we want to print, starting with the headers
     let mut columns: Vec<Vec<String>> = vec![
         vec!["".to_string()], // New column for color
+        vec!["Id".to_string()],
         vec!["Age".to_string()],
+        vec!["Name".to_string()],
         vec!["Program".to_string()],
         vec!["Runs".to_string()],
         vec!["Mean".to_string()],
         vec!["StdDev".to_string()],
         vec!["Change (%)".to_string()],
+        vec!["Failed".to_string()],
+        vec!["Output".to_string()],
+        vec!["Setup".to_string()],
+        vec!["Noise".to_string()],
+        vec!["Host".to_string()],
+        vec!["Commit".to_string()],
+        vec!["Branch".to_string()],
+        vec!["Tags".to_string()],
         vec!["Note".to_string()],
+        vec!["Source".to_string()],
 
     ];
 
     // set color for header and anything over 1% better or worse
-    let mut first_mean = measurements.first().map(|entry| entry.mean).unwrap();
+    let mut first_mean = reference.map_or(0.0, |r| measurements[r].mean);
     if first_mean <= 0.0 {
         first_mean = 0.00001f64; // avoid divide by zero
     }
@@ -73,8 +128,16 @@ fn output_table(mut wr: Box<dyn Write>, color: bool, measurements: Vec<&Entry>,
     };
 
     columns[0].extend(measurements.iter().enumerate().map(|(i, entry)| {
-        let modifier = if i == 0 {
+        let modifier = if reference.is_none() {
+            ""
+        } else if Some(i) == reference {
             bold
+        } else if pinned {
+            match baseline_change(entry.mean, first_mean, reverse).1 {
+                Some(true) => green,
+                Some(false) => red,
+                None => "",
+            }
         } else {
             if (entry.mean * 1.01 < first_mean) == reverse  {
                 green
@@ -87,25 +150,65 @@ fn output_table(mut wr: Box<dyn Write>, color: bool, measurements: Vec<&Entry>,
         modifier.to_string()
     }));
 
-    columns[1].extend(measurements.iter().map(|e| e.age(now)));
-    columns[2].extend(measurements.iter().map(|e| format!("{} {}", e.executable, e.arguments)));
-    columns[3].extend(measurements.iter().map(|e| e.runs.to_string()));
-    columns[4].extend(measurements.iter().map(|e| format!("{:.4}", e.mean)));
-    columns[5].extend(measurements.iter().map(|e|
-        (e.runs > 1).then(|| format!("{:.4}", e.stddev)).unwrap_or_default()
+    columns[1].extend(measurements.iter().map(|e| e.id.clone()));
+    columns[2].extend(measurements.iter().map(|e| e.age(now)));
+    columns[3].extend(measurements.iter().map(|e| e.name.clone()));
+    columns[4].extend(measurements.iter().map(|e| e.program()));
+    columns[5].extend(measurements.iter().map(|e| e.runs.to_string()));
+    columns[6].extend(measurements.iter().map(|e| format!("{:.4}", e.mean)));
+    columns[7].extend(measurements.iter().map(|e|
+        if e.runs > 1 { format!("{:.4}", e.stddev) } else { String::new() }
     ));
 
-    columns[6].extend(measurements.iter().enumerate().map(|(i, entry)| {
-        if i == 0 {
+    columns[8].extend(measurements.iter().enumerate().map(|(i, entry)| {
+        if reference.is_none() {
+            String::new()
+        } else if Some(i) == reference && pinned {
+            "baseline".to_string()
+        } else if Some(i) == reference {
             " ".to_string() // Empty string for the first entry
+        } else if pinned {
+            format!("{:+.2}", baseline_change(entry.mean, first_mean, reverse).0)
         } else {
             format!("{:.2}", ((first_mean - entry.mean) / first_mean) * 100.0)
         }
     }));
-    columns[7].extend(measurements.iter().map(|entry| entry.note.to_string()));
+    columns[9].extend(measurements.iter().map(|e| {
+        if e.failures > 0 {
+            format!("{} (exit {})", e.failures, join_codes(&e.exit_codes, ","))
+        } else {
+            String::new()
+        }
+    }));
+    columns[10].extend(output_status(&measurements));
+    columns[11].extend(measurements.iter().map(|e| e.setup()));
+    columns[12].extend(measurements.iter().map(|e| match e.noise {
+        Some(noise) if noise > system::NOISY => format!("busy ({:.2})", noise),
+        _ => String::new(),
+    }));
+    // only worth showing when comparing measurements from different machines
+    let hosts_differ = measurements.iter().any(|e| !e.machine.same(&measurements[0].machine));
+    columns[13].extend(measurements.iter().map(|e|
+        if hosts_differ { e.machine.hostname.clone() } else { String::new() }
+    ));
+    columns[14].extend(measurements.iter().map(|e| e.git.describe()));
+    columns[15].extend(measurements.iter().map(|e| e.git.branch.clone()));
+    columns[16].extend(measurements.iter().map(|e| e.tag_list()));
+    columns[17].extend(measurements.iter().map(|entry| entry.note.to_string()));
+    // entries from --baseline-db
+    columns[18].extend(rows.iter().map(|(_, source)| source.unwrap_or_default().to_string()));
+
+    // a row is a single line, whatever the note or arguments contain
+    for cell in columns.iter_mut().skip(1).flatten() {
+        if cell.contains(|c: char| c.is_control()) {
+            *cell = cell.replace('\n', "\\n").replace('\r', "\\r").replace(|c: char| c.is_control(), " ");
+        }
+    }
 
-    // remove any column that are empty:
+    // remove any column that are empty, except the colors which may all be plain:
+    let colors = columns.remove(0);
     columns.retain(|col| !col.iter().skip(1).all(|s| s.trim().is_empty()));
+    columns.insert(0, colors);
 
 
     // to print a nice table we will need to know max width for each column
@@ -116,56 +219,133 @@ fn output_table(mut wr: Box<dyn Write>, color: bool, measurements: Vec<&Entry>,
     // lets print the table now
     for i in 0..columns[0].len() {
         let color_prefix = &columns[0][i];
-        let row_strings: Vec<String> = columns.iter().skip(1).zip(widths.iter().skip(1)).enumerate()
-            .map(|(_j, (col, w))| format!("{:^width$}", col[i], width = w) )
+        let row_strings: Vec<String> = columns.iter().skip(1).zip(widths.iter().skip(1))
+            .map(|(col, w)| format!("{:^width$}", col[i], width = w) )
             .collect();
 
-        write!(wr, "{}{}{}\n", color_prefix, row_strings.join("|"), reset)?;
+        writeln!(wr, "{}{}{}", color_prefix, row_strings.join("|"), reset)?;
 
         // line separator between header and data
         if i == 0 {
-            write!(wr, "{}\n", widths.iter().skip(1).map(|w| "-".repeat(*w)).collect::<Vec<_>>().join("+") )?;
+            writeln!(wr, "{}", widths.iter().skip(1).map(|w| "-".repeat(*w)).collect::<Vec<_>>().join("+") )?;
         }
     }
     writeln!(wr)?;
     Ok(())
 }
 
+// change of a row in percent relative to a baseline, and whether that is better (Some(true)) or
+// worse by more than 1%. Slower is worse, unless bigger is better with --reverse
+fn baseline_change(mean: f64, base: f64, reverse: bool) -> (f64, Option<bool>) {
+    let change = (mean - base) / base * 100.0;
+    let better = if change.abs() <= 1.0 { None } else { Some((change < 0.0) != reverse) };
+    (change, better)
+}
+
+// flag output mismatches, or output that differs from the closest older run of the same command.
+// The rows are newest first, so walk them backwards remembering the last hash of each command
+fn output_status(measurements: &[&Entry]) -> Vec<String> {
+    let mut last: HashMap<String, u64> = HashMap::new();
+    let mut status = vec![String::new(); measurements.len()];
+    for (i, entry) in measurements.iter().enumerate().rev() {
+        let older = entry.output_hash.and_then(|hash| last.insert(entry.program_key(), hash));
+        if entry.mismatches > 0 {
+            status[i] = format!("{} mismatch", entry.mismatches);
+        } else if older.is_some_and(|hash| Some(hash) != entry.output_hash) {
+            status[i] = "changed".to_string();
+        }
+    }
+    status
+}
+
+fn format_hash(hash: Option<u64>) -> String {
+    hash.map(|h| format!("{:016x}", h)).unwrap_or_default()
+}
+
+fn format_noise(noise: Option<f64>) -> String {
+    noise.map(|n| format!("{:.2}", n)).unwrap_or_default()
+}
+
+fn join_codes(codes: &[i32], separator: &str) -> String {
+    codes.iter().map(|c| c.to_string()).collect::<Vec<_>>().join(separator)
+}
+
 // naive CSV escape string
 fn escape_csv(s : &str) -> String {
     s.replace(",", ".").replace("\"", "\\\"").replace("\n", " ")
 }
 
-fn output_csv(mut wr: Box<dyn Write>, measurements: Vec<&Entry>) -> Result<(), io::Error> {
-    write!(wr,"Timestamp,Executable,Arguments,Runs,Mean,StdDev,Note\n")?;
-
-    for m in measurements  {
-        write!(wr, "{},\"{}\",\"{}\",{},{},{},\"{}\"\n",
-               m.timestamp, escape_csv(&m.executable), escape_csv(&m.arguments),
-               m.runs, m.mean, m.stddev, escape_csv(&m.note))?;
+fn output_csv(mut wr: Box<dyn Write>, rows: Vec<Row>) -> Result<(), io::Error> {
+    // each label gets its own column
+    let mut keys: Vec<&str> = Vec::new();
+    for (key, _) in rows.iter().flat_map(|(m, _)| &m.labels) {
+        if !keys.contains(&key.as_str()) {
+            keys.push(key);
+        }
+    }
+    let label_columns: String = keys.iter().map(|k| format!(",\"label:{}\"", escape_csv(k))).collect();
+
+    writeln!(wr, "Id,Timestamp,Name,Executable,Arguments,Runs,Mean,StdDev,Failures,ExitCodes,OutputHash,Mismatches,Setup,Noise,\
+                  Host,Kernel,Cpu,Cores,Memory,Version,Commit,Branch,Dirty,Note,Baseline,Tags,Source{}", label_columns)?;
+
+    for (m, source) in rows  {
+        let labels: String = keys.iter().map(|k| format!(",\"{}\"", escape_csv(m.label(k).unwrap_or("")))).collect();
+        writeln!(wr, "{},{},\"{}\",\"{}\",\"{}\",{},{},{},{},\"{}\",{},{},\"{}\",{},\"{}\",\"{}\",\"{}\",{},{},\"{}\",\"{}\",\"{}\",{},\"{}\",{},\"{}\",\"{}\"{}",
+               m.id, m.timestamp, escape_csv(&m.name), escape_csv(&m.executable), escape_csv(&m.arguments),
+               m.runs, m.mean, m.stddev, m.failures, join_codes(&m.exit_codes, ";"),
+               format_hash(m.output_hash), m.mismatches, escape_csv(&m.setup()), format_noise(m.noise),
+               escape_csv(&m.machine.hostname), escape_csv(&m.machine.kernel), escape_csv(&m.machine.cpu),
+               m.machine.cores, m.machine.memory, escape_csv(&m.version),
+               m.git.commit, escape_csv(&m.git.branch), m.git.dirty, escape_csv(&m.note), m.baseline,
+               escape_csv(&m.tags.join(";")), escape_csv(source.unwrap_or_default()), labels)?;
     }
     Ok(())
 }
 
-// naive JSON escape string attempt...
+// notes, arguments and labels may contain anything but a NUL
 fn escape_json(s : &str) -> String {
-    s.replace("\"", "\\\"")
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if c.is_control() => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
 }
 
-fn output_json(mut wr: Box<dyn Write>, measurements: Vec<&Entry>) -> Result<(), io::Error> {
-    write!(wr, "[\n")?;
+fn output_json(mut wr: Box<dyn Write>, rows: Vec<Row>) -> Result<(), io::Error> {
+    writeln!(wr, "[")?;
 
-    for (i, m) in measurements.iter().enumerate() {
+    for (i, (m, source)) in rows.iter().enumerate() {
         if i != 0 {
-             write!(wr, ",\n")?;
+             writeln!(wr, ",")?;
         }
-        write!(wr, " {{\"timestamp\": {}, \"executable\": \"{}\", \"arguments\": \"{}\",  \
-                    \"runs\": {}, \"mean\": {:3.3}, \"stddev\": {:3.3}, \"note\": \"{}\"}}",
-               m.timestamp, escape_json(&m.executable), escape_json(&m.arguments),
-               m.runs, m.mean, m.stddev, escape_json(&m.note) )?;
+        write!(wr, " {{\"id\": \"{}\", \"timestamp\": {}, \"name\": \"{}\", \"executable\": \"{}\", \"arguments\": \"{}\",  \
+                    \"runs\": {}, \"mean\": {:3.3}, \"stddev\": {:3.3}, \"failures\": {}, \
+                    \"exit_codes\": [{}], \"output_hash\": \"{}\", \"mismatches\": {}, \"setup\": \"{}\", \"noise\": {}, \
+                    \"machine\": {{\"host\": \"{}\", \"kernel\": \"{}\", \"cpu\": \"{}\", \"cores\": {}, \"memory\": {}}}, \
+                    \"version\": \"{}\", \"commit\": \"{}\", \"branch\": \"{}\", \"dirty\": {}, \"note\": \"{}\", \
+                    \"baseline\": {}, \"tags\": [{}], \"labels\": {{{}}}, \"source\": \"{}\"}}",
+               m.id, m.timestamp, escape_json(&m.name), escape_json(&m.executable), escape_json(&m.arguments),
+               m.runs, m.mean, m.stddev, m.failures, join_codes(&m.exit_codes, ", "),
+               format_hash(m.output_hash), m.mismatches, escape_json(&m.setup()),
+               m.noise.map_or("null".to_string(), |n| format!("{:.2}", n)),
+               escape_json(&m.machine.hostname), escape_json(&m.machine.kernel), escape_json(&m.machine.cpu),
+               m.machine.cores, m.machine.memory, escape_json(&m.version),
+               m.git.commit, escape_json(&m.git.branch), m.git.dirty, escape_json(&m.note), m.baseline,
+               m.tags.iter().map(|t| format!("\"{}\"", escape_json(t))).collect::<Vec<_>>().join(", "),
+               m.labels.iter().map(|(k, v)| format!("\"{}\": \"{}\"", escape_json(k), escape_json(v)))
+                   .collect::<Vec<_>>().join(", "),
+               escape_json(source.unwrap_or_default()) )?;
     }
 
-    write!(wr, "\n]\n")?;
+    writeln!(wr, "\n]")?;
     Ok(())
 }
 
@@ -174,21 +354,85 @@ fn escape_xml(s : &str) -> String {
     s.replace("&", "&amp;").replace("<", "&lt;").replace(">", "&gt;")
 }
 
-fn output_xml(mut wr: Box<dyn Write>, measurements: Vec<&Entry>) -> Result<(), io::Error> {
-    write!(wr, "<Measurements>\n")?;
-
-    for m in measurements {
-        write!(wr, "  <Measurement>\n")?;
-        write!(wr, "    <Timestamp>{}</Timestamp>\n", m.timestamp)?;
-        write!(wr, "    <Executable>{}</Executable>\n", escape_xml(&m.executable))?;
-        write!(wr, "    <Arguments>{}</Arguments>\n", escape_xml(&m.arguments))?;
-        write!(wr, "    <Note>{}</Note>\n", escape_xml(&m.note))?;
-        write!(wr, "    <Runs>{}</Runs>\n", m.runs)?;
-        write!(wr, "    <Mean>{:3.3}</Mean>\n", m.mean)?;
-        write!(wr, "    <StdDev>{:3.3}</StdDev>\n", m.stddev)?;
-        write!(wr, "  </Measurement>\n")?;
+fn output_xml(mut wr: Box<dyn Write>, rows: Vec<Row>) -> Result<(), io::Error> {
+    writeln!(wr, "<Measurements>")?;
+
+    for (m, source) in rows {
+        writeln!(wr, "  <Measurement>")?;
+        writeln!(wr, "    <Id>{}</Id>", m.id)?;
+        writeln!(wr, "    <Timestamp>{}</Timestamp>", m.timestamp)?;
+        writeln!(wr, "    <Name>{}</Name>", escape_xml(&m.name))?;
+        writeln!(wr, "    <Executable>{}</Executable>", escape_xml(&m.executable))?;
+        writeln!(wr, "    <Arguments>{}</Arguments>", escape_xml(&m.arguments))?;
+        writeln!(wr, "    <Note>{}</Note>", escape_xml(&m.note))?;
+        writeln!(wr, "    <Runs>{}</Runs>", m.runs)?;
+        writeln!(wr, "    <Mean>{:3.3}</Mean>", m.mean)?;
+        writeln!(wr, "    <StdDev>{:3.3}</StdDev>", m.stddev)?;
+        writeln!(wr, "    <Failures>{}</Failures>", m.failures)?;
+        writeln!(wr, "    <ExitCodes>{}</ExitCodes>", join_codes(&m.exit_codes, " "))?;
+        writeln!(wr, "    <OutputHash>{}</OutputHash>", format_hash(m.output_hash))?;
+        writeln!(wr, "    <Mismatches>{}</Mismatches>", m.mismatches)?;
+        writeln!(wr, "    <Setup>{}</Setup>", escape_xml(&m.setup()))?;
+        writeln!(wr, "    <Noise>{}</Noise>", format_noise(m.noise))?;
+        writeln!(wr, "    <Machine>")?;
+        writeln!(wr, "      <Host>{}</Host>", escape_xml(&m.machine.hostname))?;
+        writeln!(wr, "      <Kernel>{}</Kernel>", escape_xml(&m.machine.kernel))?;
+        writeln!(wr, "      <Cpu>{}</Cpu>", escape_xml(&m.machine.cpu))?;
+        writeln!(wr, "      <Cores>{}</Cores>", m.machine.cores)?;
+        writeln!(wr, "      <Memory>{}</Memory>", m.machine.memory)?;
+        writeln!(wr, "    </Machine>")?;
+        writeln!(wr, "    <Version>{}</Version>", escape_xml(&m.version))?;
+        writeln!(wr, "    <Commit>{}</Commit>", m.git.commit)?;
+        writeln!(wr, "    <Branch>{}</Branch>", escape_xml(&m.git.branch))?;
+        writeln!(wr, "    <Dirty>{}</Dirty>", m.git.dirty)?;
+        writeln!(wr, "    <Baseline>{}</Baseline>", m.baseline)?;
+        writeln!(wr, "    <Tags>")?;
+        for tag in &m.tags {
+            writeln!(wr, "      <Tag>{}</Tag>", escape_xml(tag))?;
+        }
+        writeln!(wr, "    </Tags>")?;
+        writeln!(wr, "    <Labels>")?;
+        for (key, value) in &m.labels {
+            writeln!(wr, "      <Label key=\"{}\">{}</Label>", escape_xml(key).replace('"', "&quot;"), escape_xml(value))?;
+        }
+        writeln!(wr, "    </Labels>")?;
+        writeln!(wr, "    <Source>{}</Source>", escape_xml(source.unwrap_or_default()))?;
+        writeln!(wr, "  </Measurement>")?;
     }
 
-    write!(wr, "</Measurements>\n")?;
+    writeln!(wr, "</Measurements>")?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_baseline_change() {
+        // slower than the baseline is a regression
+        assert_eq!(baseline_change(2.0, 1.0, false), (100.0, Some(false)));
+        assert_eq!(baseline_change(0.9, 1.0, false).1, Some(true));
+        assert_eq!(baseline_change(1.005, 1.0, false).1, None);
+        // unless bigger is better
+        assert_eq!(baseline_change(2.0, 1.0, true), (100.0, Some(true)));
+        assert_eq!(baseline_change(0.9, 1.0, true).1, Some(false));
+    }
+
+    #[test]
+    fn test_escape_json() {
+        assert_eq!(escape_json("a \"b\"\\c\nd\te\u{1}"), "a \\\"b\\\"\\\\c\\nd\\te\\u0001");
+    }
+
+    #[test]
+    fn test_output_status() {
+        let entry = |executable: &str, hash: Option<u64>, mismatches: usize| Entry {
+            executable: executable.to_string(), output_hash: hash, mismatches, ..Default::default()
+        };
+        // newest first
+        let entries = [entry("a", Some(2), 0), entry("b", Some(1), 0), entry("a", None, 0),
+                       entry("a", Some(1), 1), entry("a", Some(1), 0)];
+        let rows: Vec<&Entry> = entries.iter().collect();
+        assert_eq!(output_status(&rows), vec!["changed", "", "", "1 mismatch", ""]);
+    }
+}
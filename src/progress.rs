@@ -5,6 +5,7 @@ use std::time::Instant;
 pub struct Progress {
     total: usize,
     current: usize,
+    failed: usize,
     quiet: bool,
     label: String,
     start_time: Instant,
@@ -18,7 +19,7 @@ pub struct Progress {
 impl Progress {
     pub fn new(total: usize, label: &str, quiet: bool, prev_mean: Option<f64>, prev_std: Option<f64>) -> Self {
         Progress {
-            total, current: 0, quiet, label: label.to_string(),
+            total, current: 0, failed: 0, quiet, label: label.to_string(),
             start_time: Instant::now(), mean: 0.0, m2: 0.0,
             prev_mean, prev_std, last_line_len: 0,
         }
@@ -38,9 +39,19 @@ impl Progress {
         }
     }
 
+    // a failed run counts towards progress but not the statistics
+    pub fn fail(&mut self) {
+        if !self.quiet {
+            self.current += 1;
+            self.failed += 1;
+            self.print(&self.render());
+        }
+    }
+
     fn update_stats(&mut self, time: f64) {
+        let n = self.current - self.failed;
         let delta = time - self.mean;
-        self.mean += delta / self.current as f64;
+        self.mean += delta / n as f64;
         let delta2 = time - self.mean;
         self.m2 += delta * delta2;
     }
@@ -51,9 +62,13 @@ impl Progress {
         let empty = 30 - filled;
         let counter = format!("[{:2}/{:2}]", self.current, self.total);
 
-        let stats = if self.current > 0 {
-            let std = if self.current > 1 { (self.m2 / self.current as f64).sqrt() } else { 0.0 };
-            let base = format!(", \u{03bc}={:.3} \u{03c3}={:.3}", self.mean, std);
+        let ok = self.current - self.failed;
+        let stats = if ok > 0 {
+            let std = if ok > 1 { (self.m2 / ok as f64).sqrt() } else { 0.0 };
+            let mut base = format!(", \u{03bc}={:.3} \u{03c3}={:.3}", self.mean, std);
+            if self.failed > 0 {
+                base.push_str(&format!(" ({} failed)", self.failed));
+            }
 
             if self.current < self.total {
                 // compute ETA
@@ -64,6 +79,8 @@ impl Progress {
                 // Last line doesn't need ETA, but we want to clear the previous ETA text
                 base
             }
+        } else if self.failed > 0 {
+            format!(" ({} failed)", self.failed)
         } else if let (Some(m), Some(s)) = (self.prev_mean, self.prev_std) {
             format!(", \u{03bc}={:.3} \u{03c3}={:.3}", m, s)
         } else {
@@ -0,0 +1,339 @@
+// a small regular expression matcher, enough for filtering on programs.
+// Supports . [] [^] * + ? | () ^ $ and the escapes \d \w \s.
+// The expression is compiled for a Pike VM, which runs all alternatives side by side,
+// so matching takes linear time in the length of the text and never recurses
+
+#[derive(Debug, Clone)]
+enum Node {
+    Char(char),
+    Any,
+    Class(Vec<(char, char)>, bool),
+    Start,
+    End,
+    // alternatives, each a sequence of nodes
+    Group(Vec<Vec<Node>>),
+    Repeat(Box<Node>, usize, Option<usize>),
+}
+
+#[derive(Debug, Clone)]
+enum Inst {
+    Char(char),
+    Any,
+    Class(Vec<(char, char)>, bool),
+    Start,
+    End,
+    // continue at both
+    Split(usize, usize),
+    Jump(usize),
+    Match,
+}
+
+#[derive(Debug, Clone)]
+pub struct Regex {
+    program: Vec<Inst>,
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek();
+        self.pos += 1;
+        c
+    }
+
+    fn alternatives(&mut self) -> Result<Node, String> {
+        let mut alternatives = vec![self.sequence()?];
+        while self.peek() == Some('|') {
+            self.pos += 1;
+            alternatives.push(self.sequence()?);
+        }
+        Ok(Node::Group(alternatives))
+    }
+
+    fn sequence(&mut self) -> Result<Vec<Node>, String> {
+        let mut nodes = Vec::new();
+        while let Some(c) = self.peek() {
+            if c == '|' || c == ')' {
+                break;
+            }
+            let atom = self.atom()?;
+            let (min, max) = match self.peek() {
+                Some('*') => (0, None),
+                Some('+') => (1, None),
+                Some('?') => (0, Some(1)),
+                _ => {
+                    nodes.push(atom);
+                    continue;
+                },
+            };
+            if matches!(atom, Node::Start | Node::End | Node::Repeat(..)) {
+                return Err(format!("Nothing to repeat at position {}", self.pos));
+            }
+            self.pos += 1;
+            nodes.push(Node::Repeat(Box::new(atom), min, max));
+        }
+        Ok(nodes)
+    }
+
+    fn atom(&mut self) -> Result<Node, String> {
+        match self.next() {
+            Some('(') => {
+                let group = self.alternatives()?;
+                if self.next() != Some(')') {
+                    return Err("Missing )".to_string());
+                }
+                Ok(group)
+            },
+            Some('[') => self.class(),
+            Some('.') => Ok(Node::Any),
+            Some('^') => Ok(Node::Start),
+            Some('$') => Ok(Node::End),
+            Some('\\') => self.escape(),
+            Some(c @ ('*' | '+' | '?')) => Err(format!("Nothing to repeat: {}", c)),
+            Some(c) => Ok(Node::Char(c)),
+            None => Err("Unexpected end of expression".to_string()),
+        }
+    }
+
+    fn escape(&mut self) -> Result<Node, String> {
+        let c = self.next().ok_or("Expression ends with \\".to_string())?;
+        Ok(match shorthand(c) {
+            Some(ranges) => Node::Class(ranges, false),
+            None => Node::Char(c),
+        })
+    }
+
+    fn class(&mut self) -> Result<Node, String> {
+        let negated = self.peek() == Some('^');
+        if negated {
+            self.pos += 1;
+        }
+
+        let mut ranges = Vec::new();
+        loop {
+            let c = match self.next() {
+                // a ] first in the class is just a character
+                Some(']') if !ranges.is_empty() => break,
+                Some('\\') => {
+                    let c = self.next().ok_or("Expression ends with \\".to_string())?;
+                    if let Some(shorthand) = shorthand(c) {
+                        ranges.extend(shorthand);
+                        continue;
+                    }
+                    c
+                },
+                Some(c) => c,
+                None => return Err("Missing ]".to_string()),
+            };
+            if self.peek() == Some('-') && self.chars.get(self.pos + 1).is_some_and(|c| *c != ']') {
+                self.pos += 1;
+                let end = self.next().unwrap();
+                ranges.push((c, end));
+            } else {
+                ranges.push((c, c));
+            }
+        }
+        Ok(Node::Class(ranges, negated))
+    }
+}
+
+// the ranges of \d, \w and \s, both on their own and inside []
+fn shorthand(c: char) -> Option<Vec<(char, char)>> {
+    match c {
+        'd' => Some(vec![('0', '9')]),
+        'w' => Some(vec![('a', 'z'), ('A', 'Z'), ('0', '9'), ('_', '_')]),
+        's' => Some(vec![(' ', ' '), ('\t', '\t'), ('\n', '\n'), ('\r', '\r')]),
+        _ => None,
+    }
+}
+
+fn compile(node: &Node, program: &mut Vec<Inst>) {
+    // a split whose second target is only known once what follows it is compiled
+    fn split(program: &mut Vec<Inst>) -> usize {
+        program.push(Inst::Split(program.len() + 1, 0));
+        program.len() - 1
+    }
+
+    match node {
+        Node::Char(c) => program.push(Inst::Char(*c)),
+        Node::Any => program.push(Inst::Any),
+        Node::Class(ranges, negated) => program.push(Inst::Class(ranges.clone(), *negated)),
+        Node::Start => program.push(Inst::Start),
+        Node::End => program.push(Inst::End),
+        Node::Group(alternatives) => {
+            let mut jumps = Vec::new();
+            for (i, sequence) in alternatives.iter().enumerate() {
+                let last = i + 1 == alternatives.len();
+                let fork = (!last).then(|| split(program));
+                for node in sequence {
+                    compile(node, program);
+                }
+                if let Some(fork) = fork {
+                    jumps.push(program.len());
+                    program.push(Inst::Jump(0));
+                    program[fork] = Inst::Split(fork + 1, program.len());
+                }
+            }
+            for jump in jumps {
+                program[jump] = Inst::Jump(program.len());
+            }
+        },
+        Node::Repeat(node, min, max) => {
+            for _ in 0..*min {
+                compile(node, program);
+            }
+            match max {
+                None => {
+                    let fork = split(program);
+                    compile(node, program);
+                    program.push(Inst::Jump(fork));
+                    program[fork] = Inst::Split(fork + 1, program.len());
+                },
+                Some(max) => for _ in *min..*max {
+                    let fork = split(program);
+                    compile(node, program);
+                    program[fork] = Inst::Split(fork + 1, program.len());
+                },
+            }
+        },
+    }
+}
+
+impl Regex {
+    pub fn new(expression: &str) -> Result<Regex, String> {
+        let mut parser = Parser { chars: expression.chars().collect(), pos: 0 };
+        let root = parser.alternatives()?;
+        if parser.pos < parser.chars.len() {
+            return Err(format!("Unmatched ) at position {}", parser.pos));
+        }
+        let mut program = Vec::new();
+        compile(&root, &mut program);
+        program.push(Inst::Match);
+        Ok(Regex { program })
+    }
+
+    // true if the expression matches anywhere in text
+    pub fn is_match(&self, text: &str) -> bool {
+        let text: Vec<char> = text.chars().collect();
+        // position each instruction was last added for, so it runs at most once per position
+        let mut added = vec![usize::MAX; self.program.len()];
+        let mut threads = Vec::new();
+        let mut next = Vec::new();
+        for pos in 0..=text.len() {
+            // a match can start anywhere
+            if self.add(&mut threads, &mut added, 0, pos, text.len()) {
+                return true;
+            }
+            for &pc in &threads {
+                let c = text.get(pos);
+                let step = match &self.program[pc] {
+                    Inst::Char(expected) => c == Some(expected),
+                    Inst::Any => c.is_some(),
+                    Inst::Class(ranges, negated) => c
+                        .is_some_and(|c| ranges.iter().any(|(lo, hi)| lo <= c && c <= hi) != *negated),
+                    _ => false,
+                };
+                if step && self.add(&mut next, &mut added, pc + 1, pos + 1, text.len()) {
+                    return true;
+                }
+            }
+            std::mem::swap(&mut threads, &mut next);
+            next.clear();
+        }
+        false
+    }
+
+    // add a thread at pc, following jumps, splits and assertions up to the instructions
+    // that consume a character. Returns true if that reaches a match
+    fn add(&self, threads: &mut Vec<usize>, added: &mut [usize], pc: usize, pos: usize, len: usize) -> bool {
+        let mut stack = vec![pc];
+        while let Some(pc) = stack.pop() {
+            if added[pc] == pos {
+                continue;
+            }
+            added[pc] = pos;
+            match &self.program[pc] {
+                Inst::Match => return true,
+                Inst::Jump(to) => stack.push(*to),
+                Inst::Split(first, second) => stack.extend([*second, *first]),
+                Inst::Start => if pos == 0 {
+                    stack.push(pc + 1);
+                },
+                Inst::End => if pos == len {
+                    stack.push(pc + 1);
+                },
+                _ => threads.push(pc),
+            }
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(expression: &str, text: &str) -> bool {
+        Regex::new(expression).unwrap().is_match(text)
+    }
+
+    #[test]
+    fn test_literals() {
+        assert!(matches("fib", "python3 fib.py 30"));
+        assert!(!matches("fob", "python3 fib.py 30"));
+        assert!(matches("", "anything"));
+        assert!(matches("fib\\.py", "fib.py"));
+        assert!(!matches("fib\\.py", "fibxpy"));
+    }
+
+    #[test]
+    fn test_operators() {
+        assert!(matches("^py(thon|py)3 ", "pypy3 x.py"));
+        assert!(matches("^py(thon|py)3 ", "python3 x.py"));
+        assert!(!matches("^py(thon|py)3 ", "/usr/bin/python3 x.py"));
+        assert!(matches("a.*b$", "xxaxxb"));
+        assert!(!matches("a.*b$", "xxaxxbx"));
+        assert!(matches("^a+b?c*$", "aaac"));
+        assert!(!matches("^a+b?c*$", "bc"));
+        assert!(matches("[0-9]+ms", "took 15ms"));
+        assert!(matches("^[^/]+$", "a.out"));
+        assert!(!matches("^[^/]+$", "./a.out"));
+        assert!(matches("\\d\\d\\s\\w", "30 x"));
+        assert!(matches("^[\\d.]+$", "3.14"));
+        assert!(!matches("[\\d]", "d"));
+        assert!(matches("^[^\\s]+$", "a.out"));
+        assert!(!matches("^[^\\s]+$", "a out"));
+        assert!(matches("[\\]]", "]"));
+        assert!(matches("^(a*)*$", "aaa"));
+        assert!(matches("^(ab)?c", "c"));
+    }
+
+    #[test]
+    fn test_no_backtracking() {
+        // exponential for a backtracking matcher
+        let text = "a".repeat(64);
+        assert!(!matches("^(a*)*b", &text));
+        assert!(!matches("^(a|a)*(a|aa)*b$", &text));
+        // deep recursion for a recursive one
+        let text = "x".repeat(100_000) + "y";
+        assert!(matches("x*y$", &text));
+    }
+
+    #[test]
+    fn test_errors() {
+        assert!(Regex::new("(a").is_err());
+        assert!(Regex::new("a)").is_err());
+        assert!(Regex::new("[a").is_err());
+        assert!(Regex::new("*a").is_err());
+        assert!(Regex::new("a**").is_err());
+        assert!(Regex::new("a\\").is_err());
+    }
+}
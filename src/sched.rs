@@ -0,0 +1,88 @@
+// scheduling controls for the benchmarked program, talks to libc directly to avoid dependencies
+#[cfg(target_os = "linux")]
+use std::io;
+use std::process::Command;
+
+// cpu_set_t in glibc and musl is 1024 bits
+const CPU_SETSIZE: usize = 1024;
+
+// parse a cpu list such as "2,3" or "0-3,6"
+pub fn parse_cpus(s: &str) -> Result<Vec<usize>, String> {
+    let mut cpus = Vec::new();
+    for part in s.split(',') {
+        let (first, last) = part.split_once('-').unwrap_or((part, part));
+        let first = first.trim().parse::<usize>().map_err(|_| format!("Bad cpu: {}", part))?;
+        let last = last.trim().parse::<usize>().map_err(|_| format!("Bad cpu: {}", part))?;
+        if first > last || last >= CPU_SETSIZE {
+            return Err(format!("Bad cpu range: {}", part));
+        }
+        cpus.extend(first..=last);
+    }
+    cpus.sort();
+    cpus.dedup();
+    Ok(cpus)
+}
+
+#[cfg(target_os = "linux")]
+mod sys {
+    extern "C" {
+        pub fn sched_setaffinity(pid: i32, size: usize, mask: *const u64) -> i32;
+        pub fn setpriority(which: i32, who: u32, prio: i32) -> i32;
+    }
+    pub const PRIO_PROCESS: i32 = 0;
+}
+
+// make the child run on the given cpus and with the given niceness, nothing is changed for mesa itself
+#[cfg(target_os = "linux")]
+pub fn apply(command: &mut Command, cpus: &[usize], nice: Option<i32>) -> Result<(), String> {
+    use std::os::unix::process::CommandExt;
+
+    if cpus.is_empty() && nice.is_none() {
+        return Ok(());
+    }
+
+    let mut mask = [0u64; CPU_SETSIZE / 64];
+    for cpu in cpus {
+        mask[cpu / 64] |= 1 << (cpu % 64);
+    }
+    let pin = !cpus.is_empty();
+
+    // SAFETY: the closure runs between fork and exec, it only makes two
+    // async-signal-safe system calls and does not allocate
+    unsafe {
+        command.pre_exec(move || {
+            if pin && sys::sched_setaffinity(0, std::mem::size_of_val(&mask), mask.as_ptr()) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            if let Some(n) = nice {
+                if sys::setpriority(sys::PRIO_PROCESS, 0, n) != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+            Ok(())
+        });
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn apply(_command: &mut Command, cpus: &[usize], nice: Option<i32>) -> Result<(), String> {
+    if !cpus.is_empty() || nice.is_some() {
+        return Err("CPU pinning and nice are only supported on Linux".to_string());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_cpus() {
+        assert_eq!(parse_cpus("2,3").unwrap(), vec![2, 3]);
+        assert_eq!(parse_cpus("0-3,2,6").unwrap(), vec![0, 1, 2, 3, 6]);
+        assert!(parse_cpus("").is_err());
+        assert!(parse_cpus("3-1").is_err());
+        assert!(parse_cpus("5000").is_err());
+    }
+}
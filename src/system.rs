@@ -0,0 +1,210 @@
+// what we can learn about the machine from /proc and /sys, only Linux is supported
+use std::collections::HashMap;
+use std::fs;
+use std::thread;
+use std::time::Duration;
+
+// entries with a noise score above this were measured on a busy machine
+pub const NOISY: f64 = 0.5;
+
+// process using more than this fraction of a cpu is reported as busy
+const BUSY_PROCESS: f64 = 0.1;
+
+// USER_HZ, the unit of cpu times in /proc, is 100 on all mainstream architectures
+const CLOCK_TICKS: f64 = 100.0;
+
+fn read(path: &str) -> Option<String> {
+    fs::read_to_string(path).ok().map(|s| s.trim().to_string())
+}
+
+pub fn cores() -> usize {
+    thread::available_parallelism().map(|n| n.get()).unwrap_or(1)
+}
+
+fn load_average() -> Option<f64> {
+    read("/proc/loadavg")?.split_whitespace().next()?.parse().ok()
+}
+
+fn swap_pages() -> Option<u64> {
+    let vmstat = read("/proc/vmstat")?;
+    let pages = vmstat.lines()
+        .filter_map(|line| line.split_once(' '))
+        .filter(|(key, _)| *key == "pswpin" || *key == "pswpout")
+        .filter_map(|(_, value)| value.parse::<u64>().ok())
+        .sum();
+    Some(pages)
+}
+
+// cpu time in ticks of every process except ourselves, keyed by pid
+fn process_times() -> HashMap<u32, (String, u64)> {
+    let me = std::process::id();
+    let mut times = HashMap::new();
+    let Ok(dir) = fs::read_dir("/proc") else {
+        return times;
+    };
+
+    for pid in dir.flatten().filter_map(|e| e.file_name().to_str()?.parse::<u32>().ok()) {
+        if pid == me {
+            continue;
+        }
+        // the name is in parentheses and may contain spaces, the rest follows after it
+        let Some(stat) = read(&format!("/proc/{}/stat", pid)) else { continue };
+        let (Some(open), Some(close)) = (stat.find('('), stat.rfind(')')) else { continue };
+        let fields: Vec<&str> = stat[close + 1..].split_whitespace().collect();
+        if let (Some(utime), Some(stime)) = (fields.get(11), fields.get(12)) {
+            let ticks = utime.parse::<u64>().unwrap_or(0) + stime.parse::<u64>().unwrap_or(0);
+            times.insert(pid, (stat[open + 1..close].to_string(), ticks));
+        }
+    }
+    times
+}
+
+// the machine a measurement was taken on
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Machine {
+    pub hostname: String,
+    pub kernel: String,
+    pub cpu: String,
+    pub cores: usize,
+    pub memory: u64,
+}
+
+impl Machine {
+    pub fn current() -> Machine {
+        let cpuinfo = read("/proc/cpuinfo").unwrap_or_default();
+        let cpu = cpuinfo.lines()
+            .filter_map(|line| line.split_once(':'))
+            .find(|(key, _)| key.trim() == "model name" || key.trim() == "Model")
+            .map(|(_, value)| value.trim().to_string())
+            .unwrap_or_default();
+
+        // MemTotal is in kB
+        let memory = read("/proc/meminfo").unwrap_or_default().lines()
+            .find_map(|line| line.strip_prefix("MemTotal:"))
+            .and_then(|value| value.split_whitespace().next()?.parse::<u64>().ok())
+            .map(|kb| kb * 1024)
+            .unwrap_or(0);
+
+        Machine {
+            hostname: read("/proc/sys/kernel/hostname").unwrap_or_default(),
+            kernel: read("/proc/sys/kernel/osrelease").unwrap_or_default(),
+            cpu,
+            cores: cores(),
+            memory,
+        }
+    }
+
+    // kernel and reported memory change with updates, the hardware does not
+    pub fn same(&self, other: &Machine) -> bool {
+        self.hostname == other.hostname && self.cpu == other.cpu && self.cores == other.cores
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Noise {
+    pub load: f64,
+    pub cores: usize,
+    pub governors: Vec<String>,
+    pub turbo: Option<bool>,
+    pub swapped: u64,
+    pub busy: Vec<(String, f64)>,
+}
+
+impl Noise {
+    // look at the machine for a short while, returns None if this is not Linux
+    pub fn sample(interval: Duration) -> Option<Noise> {
+        let load = load_average()?;
+        let swap_before = swap_pages().unwrap_or(0);
+        let before = process_times();
+        thread::sleep(interval);
+        let after = process_times();
+        let swap_after = swap_pages().unwrap_or(0);
+
+        let mut busy: Vec<(String, f64)> = after.iter()
+            .filter_map(|(pid, (name, ticks))| {
+                let (_, prev) = before.get(pid)?;
+                let usage = ticks.saturating_sub(*prev) as f64 / CLOCK_TICKS / interval.as_secs_f64();
+                (usage > BUSY_PROCESS).then(|| (name.clone(), usage))
+            })
+            .collect();
+        busy.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+        let mut governors: Vec<String> = fs::read_dir("/sys/devices/system/cpu").into_iter()
+            .flatten()
+            .flatten()
+            .filter_map(|e| read(&format!("{}/cpufreq/scaling_governor", e.path().display())))
+            .collect();
+        governors.sort();
+        governors.dedup();
+
+        // intel_pstate reports the opposite of everyone else
+        let turbo = read("/sys/devices/system/cpu/intel_pstate/no_turbo").map(|s| s == "0")
+            .or_else(|| read("/sys/devices/system/cpu/cpufreq/boost").map(|s| s == "1"));
+
+        Some(Noise {
+            load,
+            cores: cores(),
+            governors,
+            turbo,
+            swapped: swap_after.saturating_sub(swap_before),
+            busy,
+        })
+    }
+
+    // roughly how much of the machine is used by others, 0 is an idle machine
+    pub fn score(&self) -> f64 {
+        let cores = self.cores.max(1) as f64;
+        let busy: f64 = self.busy.iter().map(|(_, usage)| usage).sum();
+        let swapping = if self.swapped > 0 { 1.0 } else { 0.0 };
+        (self.load / cores).max(busy / cores) + swapping
+    }
+
+    pub fn warnings(&self) -> Vec<String> {
+        let mut warnings = Vec::new();
+        if self.load > self.cores as f64 * NOISY {
+            warnings.push(format!("load average is {:.2} on {} cores", self.load, self.cores));
+        }
+        if let Some(other) = self.governors.iter().find(|g| *g != "performance") {
+            warnings.push(format!("cpu frequency governor is '{}', not 'performance'", other));
+        }
+        if self.turbo == Some(true) {
+            warnings.push("turbo boost is enabled".to_string());
+        }
+        if self.swapped > 0 {
+            warnings.push(format!("system is swapping ({} pages)", self.swapped));
+        }
+        for (name, usage) in &self.busy {
+            warnings.push(format!("process '{}' is using {:.0}% cpu", name, usage * 100.0));
+        }
+        warnings
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_same_machine() {
+        let machine = Machine { hostname: "ci-1".to_string(), cpu: "Ryzen".to_string(), cores: 8, ..Default::default() };
+        let updated = Machine { kernel: "6.1".to_string(), memory: 1 << 30, ..machine.clone() };
+        assert!(machine.same(&updated));
+        let other = Machine { hostname: "laptop".to_string(), ..machine.clone() };
+        assert!(!machine.same(&other));
+    }
+
+    #[test]
+    fn test_score() {
+        let mut noise = Noise { cores: 4, ..Default::default() };
+        assert_eq!(noise.score(), 0.0);
+        assert!(noise.warnings().is_empty());
+
+        noise.load = 2.0;
+        assert_eq!(noise.score(), 0.5);
+        noise.busy = vec![("make".to_string(), 3.0)];
+        assert_eq!(noise.score(), 0.75);
+        noise.swapped = 10;
+        assert_eq!(noise.score(), 1.75);
+        assert_eq!(noise.warnings().len(), 2);
+    }
+}
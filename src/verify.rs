@@ -0,0 +1,42 @@
+// program output verification, we only keep a hash of the output around
+
+// normalize output so that line endings and trailing whitespace do not matter
+pub fn normalize(text: &str) -> String {
+    let lines: Vec<&str> = text.lines().map(|line| line.trim_end()).collect();
+    lines.join("\n").trim_end_matches('\n').to_string()
+}
+
+// FNV-1a, good enough to detect changes and stable across platforms and releases
+pub fn hash(text: &str) -> u64 {
+    text.bytes().fold(0xcbf29ce484222325u64, |h, b| (h ^ b as u64).wrapping_mul(0x100000001b3))
+}
+
+pub fn hash_output(output: &[u8]) -> u64 {
+    hash(&normalize(&String::from_utf8_lossy(output)))
+}
+
+pub fn hash_file(filename: &str) -> Result<u64, String> {
+    let content = std::fs::read(filename)
+        .map_err(|e| format!("Failed to read expected output {}: {}", filename, e))?;
+    Ok(hash_output(&content))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(normalize("a  \r\nb\n\n\n"), "a\nb");
+        assert_eq!(normalize(""), "");
+        assert_eq!(hash_output(b"42\r\n"), hash_output(b"42"));
+        assert_ne!(hash_output(b"42"), hash_output(b"43"));
+    }
+
+    #[test]
+    fn test_hash() {
+        // reference values for FNV-1a 64
+        assert_eq!(hash(""), 0xcbf29ce484222325);
+        assert_eq!(hash("a"), 0xaf63dc4c8601ec8c);
+    }
+}